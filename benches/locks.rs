@@ -0,0 +1,139 @@
+//! Swap vs CAS vs TTAS vs backoff vs jitter acquisition under 1-8 contending threads,
+//! `Backoff` with more or fewer pauses per step, plain vs `PaddedSpinLock` arrays
+//! for false sharing, and the cost of a single uncontended lock/unlock.
+//!
+//! Run with `cargo bench`; throughput is reported in locks/sec.
+
+use std::{hint::black_box, thread};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use my_spin_lock::{bench::uncontended_cycles, Backoff, Guard, PaddedSpinLock, SpinLock};
+
+/// One of the `SpinLock::lock_with_*` methods.
+type LockFn = for<'a> fn(&'a SpinLock<u64>) -> Guard<'a, u64>;
+
+/// Lock acquisitions per thread per iteration.
+const LOCKS_PER_THREAD: u64 = 1_000;
+
+/// `threads` threads each take the lock `LOCKS_PER_THREAD` times with `lock`
+/// and do a tiny critical section.
+fn contend(threads: usize, lock: LockFn) {
+    let x = SpinLock::new(0);
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for _ in 0..LOCKS_PER_THREAD {
+                    *lock(black_box(&x)) += 1;
+                }
+            });
+        }
+    });
+    assert_eq!(x.into_inner(), threads as u64 * LOCKS_PER_THREAD);
+}
+
+fn acquisition(c: &mut Criterion) {
+    let strategies: [(&str, LockFn); 5] = [
+        ("swap", SpinLock::lock_with_swap),
+        ("cas", SpinLock::lock_with_cas),
+        ("ttas", SpinLock::lock_ttas),
+        ("backoff", SpinLock::lock_with_backoff),
+        ("jitter", SpinLock::lock_with_jitter),
+    ];
+    let mut group = c.benchmark_group("acquisition");
+    for threads in [1, 2, 4, 8] {
+        group.throughput(Throughput::Elements(threads as u64 * LOCKS_PER_THREAD));
+        for (name, lock) in strategies {
+            group.bench_with_input(BenchmarkId::new(name, threads), &threads, |b, &threads| {
+                b.iter(|| contend(threads, lock))
+            });
+        }
+    }
+    group.finish();
+}
+
+/// `lock_with` and `Backoff::with_pauses`, 4 threads: how much spinning per
+/// backoff step suits this CPU.
+fn backoff_pauses(c: &mut Criterion) {
+    const THREADS: usize = 4;
+    let mut group = c.benchmark_group("backoff_pauses");
+    group.throughput(Throughput::Elements(THREADS as u64 * LOCKS_PER_THREAD));
+    for pauses in [1, 4, 16] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(pauses),
+            &pauses,
+            |b, &pauses| {
+                b.iter(|| {
+                    let x = SpinLock::new(0u64);
+                    thread::scope(|s| {
+                        for _ in 0..THREADS {
+                            s.spawn(|| {
+                                let mut backoff = Backoff::with_pauses(pauses);
+                                for _ in 0..LOCKS_PER_THREAD {
+                                    *black_box(&x).lock_with(&mut backoff) += 1;
+                                }
+                            });
+                        }
+                    });
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// One lock per thread, no contention on the locks themselves: any slowdown
+/// of the plain array compared to the padded one is false sharing.
+fn false_sharing(c: &mut Criterion) {
+    const THREADS: usize = 4;
+    let mut group = c.benchmark_group("false_sharing");
+    group.throughput(Throughput::Elements(THREADS as u64 * LOCKS_PER_THREAD));
+    group.bench_function("plain", |b| {
+        b.iter(|| {
+            let locks: [SpinLock<u64>; THREADS] = Default::default();
+            thread::scope(|s| {
+                for lock in &locks {
+                    s.spawn(move || {
+                        for _ in 0..LOCKS_PER_THREAD {
+                            *black_box(lock).lock_with_cas() += 1;
+                        }
+                    });
+                }
+            });
+        })
+    });
+    group.bench_function("padded", |b| {
+        b.iter(|| {
+            let locks: [PaddedSpinLock<u64>; THREADS] = Default::default();
+            thread::scope(|s| {
+                for lock in &locks {
+                    s.spawn(move || {
+                        for _ in 0..LOCKS_PER_THREAD {
+                            *black_box(lock).lock_with_cas() += 1;
+                        }
+                    });
+                }
+            });
+        })
+    });
+    group.finish();
+}
+
+/// Just the fast path, see `uncontended_cycles`.
+fn uncontended(c: &mut Criterion) {
+    let x = SpinLock::new(0);
+    let mut group = c.benchmark_group("uncontended");
+    group.throughput(Throughput::Elements(LOCKS_PER_THREAD));
+    group.bench_function("cas", |b| {
+        b.iter(|| uncontended_cycles(&x, LOCKS_PER_THREAD))
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    acquisition,
+    backoff_pauses,
+    false_sharing,
+    uncontended
+);
+criterion_main!(benches);
@@ -0,0 +1,67 @@
+//! Drives one `SpinLock` from a few threads with a fuzzer-chosen sequence of
+//! operations and checks that at most one guard is ever alive.
+//!
+//! Run with `cargo fuzz run spinlock` from the repository root.
+#![no_main]
+
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use libfuzzer_sys::fuzz_target;
+use my_spin_lock::{Guard, SpinLock};
+
+const THREADS: usize = 3;
+
+/// A guard that also counts how many guards are alive right now.
+struct Checked<'a> {
+    guard: Guard<'a, u64>,
+    live: &'a AtomicUsize,
+}
+
+impl<'a> Checked<'a> {
+    fn new(guard: Guard<'a, u64>, live: &'a AtomicUsize) -> Self {
+        let before = live.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(before, 0, "two guards alive at once");
+        Self { guard, live }
+    }
+}
+
+impl Drop for Checked<'_> {
+    fn drop(&mut self) {
+        // Before the guard itself releases the lock.
+        self.live.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fuzz_target!(|ops: &[u8]| {
+    let lock = SpinLock::new(0u64);
+    let live = AtomicUsize::new(0);
+    let expected = AtomicUsize::new(0);
+    thread::scope(|s| {
+        for chunk in ops.chunks(ops.len().div_ceil(THREADS).max(1)) {
+            let (lock, live, expected) = (&lock, &live, &expected);
+            s.spawn(move || {
+                for &op in chunk {
+                    let guard = match op % 4 {
+                        0 => Some(lock.lock_with_cas()),
+                        1 => Some(lock.lock_with_swap()),
+                        2 => lock.try_lock(),
+                        _ => lock.try_lock_spins(usize::from(op >> 2)),
+                    };
+                    if let Some(guard) = guard {
+                        let mut checked = Checked::new(guard, live);
+                        *checked.guard += 1;
+                        expected.fetch_add(1, Ordering::Relaxed);
+                        if op & 0x80 != 0 {
+                            // Hold it a little longer, so others pile up behind.
+                            thread::yield_now();
+                        }
+                    }
+                }
+            });
+        }
+    });
+    assert_eq!(lock.into_inner(), expected.into_inner() as u64);
+});
@@ -0,0 +1,85 @@
+/// Generates `with_lock` shortcuts for the `SpinLock` fields of a struct.
+///
+/// Each line turns into a method that locks one field and runs a closure on
+/// it, so callers write `stats.with_hits(|h| *h += 1)` instead of
+/// `stats.hits.with_lock(|h| *h += 1)`, and the fields themselves can stay
+/// private. `macro_rules!` can't glue `with_` onto a field name, so the
+/// method name is spelled out:
+///
+/// ```
+/// use my_spin_lock::{spinlock_accessors, SpinLock};
+///
+/// struct Stats {
+///     hits: SpinLock<u64>,
+///     names: SpinLock<Vec<String>>,
+/// }
+///
+/// spinlock_accessors! {
+///     impl Stats {
+///         pub fn with_hits => hits: u64;
+///         pub fn with_names => names: Vec<String>;
+///     }
+/// }
+///
+/// let stats = Stats { hits: SpinLock::new(0), names: SpinLock::new(Vec::new()) };
+/// stats.with_hits(|h| *h += 1);
+/// let n = stats.with_names(|names| {
+///     names.push(String::from("a"));
+///     names.len()
+/// });
+/// assert_eq!((stats.with_hits(|h| *h), n), (1, 1));
+/// ```
+#[macro_export]
+macro_rules! spinlock_accessors {
+    (impl $ty:ty { $($vis:vis fn $method:ident => $field:ident: $field_ty:ty;)* }) => {
+        impl $ty {
+            $(
+                /// Runs `f` with this field's lock held, see `SpinLock::with_lock`.
+                $vis fn $method<R>(&self, f: impl FnOnce(&mut $field_ty) -> R) -> R {
+                    self.$field.with_lock(f)
+                }
+            )*
+        }
+    };
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::thread;
+
+    use crate::SpinLock;
+
+    struct Account {
+        balance: SpinLock<i64>,
+        log: SpinLock<Vec<i64>>,
+    }
+
+    spinlock_accessors! {
+        impl Account {
+            fn with_balance => balance: i64;
+            fn with_log => log: Vec<i64>;
+        }
+    }
+
+    #[test]
+    fn generated_accessors_from_several_threads() {
+        let account = Account {
+            balance: SpinLock::new(0),
+            log: SpinLock::new(Vec::new()),
+        };
+        thread::scope(|s| {
+            for i in 1..=4 {
+                let account = &account;
+                s.spawn(move || {
+                    for _ in 0..100 {
+                        account.with_balance(|b| *b += i);
+                        account.with_log(|log| log.push(i));
+                    }
+                });
+            }
+        });
+        assert_eq!(account.with_balance(|b| *b), 1000);
+        assert_eq!(account.with_log(|log| log.iter().sum::<i64>()), 1000);
+        assert_eq!(account.with_log(|log| log.len()), 400);
+    }
+}
@@ -0,0 +1,68 @@
+//! The process-wide spin count behind `SpinLock::new_autotuned`.
+
+use core::{
+    hint::spin_loop,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use std::time::{Duration, Instant};
+
+use crate::SpinOnce;
+
+/// The spin counts to choose from, the default `ADAPTIVE_SPINS` among them.
+const CANDIDATES: [usize; 8] = [16, 32, 64, 128, 256, 512, 1024, 2048];
+
+static TUNE: SpinOnce = SpinOnce::new();
+static SPINS: AtomicUsize = AtomicUsize::new(0);
+
+/// The tuned spin count, measuring it first if nobody has yet. Every thread
+/// gets the same number for the life of the process.
+pub(crate) fn spins() -> usize {
+    // `call_once` orders the store before every return, `Relaxed` is fine.
+    TUNE.call_once(|| SPINS.store(measure(), Ordering::Relaxed));
+    SPINS.load(Ordering::Relaxed)
+}
+
+/// Spinning only beats yielding while it is cheaper than the yield would
+/// have been, so pick the largest candidate whose spins together cost no
+/// more than one `yield_now()` on this machine. Both timings are the best
+/// of a few runs, to keep an unlucky preemption from deciding; all of it
+/// takes well under a millisecond.
+fn measure() -> usize {
+    const ROUNDS: u32 = 64;
+    let best = |f: &dyn Fn()| {
+        (0..3)
+            .map(|_| {
+                let start = Instant::now();
+                f();
+                start.elapsed()
+            })
+            .min()
+            .unwrap_or(Duration::ZERO)
+    };
+    let per_yield = best(&|| (0..ROUNDS).for_each(|_| std::thread::yield_now())) / ROUNDS;
+    let per_spin = best(&|| (0..ROUNDS * 16).for_each(|_| spin_loop())) / (ROUNDS * 16);
+    let budget = per_yield.as_nanos() / per_spin.as_nanos().max(1);
+    CANDIDATES
+        .into_iter()
+        .take_while(|&spins| spins as u128 <= budget)
+        .last()
+        .unwrap_or(CANDIDATES[0])
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn tunes_once_to_a_candidate() {
+        let seen: Vec<_> = thread::scope(|s| {
+            let handles: Vec<_> = (0..4).map(|_| s.spawn(spins)).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert!(CANDIDATES.contains(&seen[0]));
+        assert!(seen.iter().all(|&n| n == seen[0]));
+        assert_eq!(spins(), seen[0]);
+    }
+}
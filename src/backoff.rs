@@ -0,0 +1,122 @@
+/// Escalating wait strategy for contended spin loops, in the spirit of
+/// crossbeam's `Backoff`.
+///
+/// Each call to `snooze()` waits a bit longer than the previous one:
+/// first it spins with `spin_loop()` 1, 2, 4 ... `2^SPIN_LIMIT` times,
+/// after that it hands the CPU back to the OS with `yield_now()`
+/// (without the `std` feature there is no OS to yield to, so it keeps spinning).
+/// A `Backoff` can be reused across acquisitions by calling `reset()`.
+///
+/// How long one `spin_loop()` takes depends a lot on the CPU (a PAUSE is ~10
+/// cycles on older x86 cores and ~140 on Skylake and later), so
+/// `with_pauses` scales every spinning step to taste.
+#[derive(Debug)]
+pub struct Backoff {
+    step: u32,
+    pauses: u32,
+}
+
+/// `iters` times `spin_loop()`, i.e. PAUSE on x86, YIELD (or ISB) on ARM and a
+/// no-op on targets without such a hint.
+#[inline]
+pub fn cpu_relax(iters: u32) {
+    for _ in 0..iters {
+        core::hint::spin_loop();
+    }
+}
+
+impl Backoff {
+    /// Past this step we stop spinning and start yielding.
+    const SPIN_LIMIT: u32 = 6;
+    /// `step` stops growing here, so it can never overflow.
+    const YIELD_LIMIT: u32 = 10;
+
+    pub const fn new() -> Self {
+        Self::with_pauses(1)
+    }
+
+    /// A backoff whose spinning steps issue `pauses` times as many
+    /// `spin_loop()`s: `pauses`, `2 * pauses`, `4 * pauses` ... The yielding
+    /// steps are the same as usual.
+    pub const fn with_pauses(pauses: u32) -> Self {
+        Self { step: 0, pauses }
+    }
+
+    /// Starts over from the shortest wait.
+    pub fn reset(&mut self) {
+        self.step = 0;
+    }
+
+    /// How far the backoff has escalated, i.e. how many times `snooze()`
+    /// has been called since the last `reset()` (saturating).
+    pub fn step(&self) -> u32 {
+        self.step
+    }
+
+    /// Returns `true` once we are yielding to the OS instead of spinning,
+    /// a hint that the lock holder may have been descheduled.
+    pub fn is_yielding(&self) -> bool {
+        self.step > Self::SPIN_LIMIT
+    }
+
+    /// Waits once, escalating from busy spinning to `yield_now()`.
+    pub fn snooze(&mut self) {
+        if self.step <= Self::SPIN_LIMIT {
+            cpu_relax(self.pauses.saturating_mul(1 << self.step));
+        } else {
+            #[cfg(feature = "std")]
+            std::thread::yield_now();
+            #[cfg(not(feature = "std"))]
+            core::hint::spin_loop();
+        }
+        if self.step <= Self::YIELD_LIMIT {
+            self.step += 1;
+        }
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snooze_escalates_to_yield() {
+        let mut b = Backoff::new();
+        for _ in 0..=Backoff::SPIN_LIMIT {
+            assert!(!b.is_yielding());
+            b.snooze();
+        }
+        assert!(b.is_yielding());
+        for _ in 0..100 {
+            b.snooze();
+        }
+        assert_eq!(b.step(), Backoff::YIELD_LIMIT + 1);
+        b.reset();
+        assert_eq!(b.step(), 0);
+    }
+
+    #[cfg(not(loom))]
+    #[test]
+    fn tuned_pauses_still_acquire() {
+        let x = crate::SpinLock::new(0);
+        std::thread::scope(|s| {
+            for pauses in [0, 1, 16] {
+                let x = &x;
+                s.spawn(move || {
+                    let mut backoff = Backoff::with_pauses(pauses);
+                    for _ in 0..1000 {
+                        *x.lock_with(&mut backoff) += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(x.into_inner(), 3000);
+        assert_eq!(Backoff::default().pauses, 1);
+    }
+}
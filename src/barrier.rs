@@ -0,0 +1,122 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::Backoff;
+
+/// Makes `n` threads wait for each other, spinning instead of blocking in the
+/// OS: everyone calling `wait()` spins until the `n`th one arrives, then all
+/// of them continue. The barrier is reusable for any number of rounds.
+///
+/// Arrivals are counted in `arrived`, and the last one to arrive resets the
+/// count and bumps `generation`, which is what the others are spinning on.
+///
+/// ```
+/// use my_spin_lock::SpinBarrier;
+///
+/// let barrier = SpinBarrier::new(3);
+/// std::thread::scope(|s| {
+///     for _ in 0..3 {
+///         s.spawn(|| barrier.wait());
+///     }
+/// });
+/// ```
+#[derive(Debug)]
+pub struct SpinBarrier {
+    n: usize,
+    arrived: AtomicUsize,
+    generation: AtomicUsize,
+}
+
+impl SpinBarrier {
+    /// A barrier for `n` threads. Like `std::sync::Barrier`, `n` of 0 acts
+    /// like 1: `wait()` never waits.
+    pub const fn new(n: usize) -> Self {
+        Self {
+            n,
+            arrived: AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+        }
+    }
+
+    /// Spins until all `n` threads have called `wait()`. Returns `true` for
+    /// exactly one of them each round (the last to arrive), like
+    /// `BarrierWaitResult::is_leader`.
+    pub fn wait(&self) -> bool {
+        // Read before arriving: the round can't end without us.
+        let generation = self.generation.load(Ordering::Relaxed);
+        // `AcqRel` so the leader sees everything the others did before
+        // arriving, and then publishes it all with its `Release` below.
+        if self.arrived.fetch_add(1, Ordering::AcqRel) + 1 >= self.n {
+            // Nobody arrives for the next round until they see the new generation.
+            self.arrived.store(0, Ordering::Relaxed);
+            self.generation.fetch_add(1, Ordering::Release);
+            return true;
+        }
+        let mut backoff = Backoff::new();
+        while self.generation.load(Ordering::Acquire) == generation {
+            backoff.snooze();
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Mutex,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+
+    #[test]
+    fn nobody_leaves_before_everyone_arrived() {
+        const N: usize = 4;
+        let barrier = SpinBarrier::new(N);
+        let times = Mutex::new(Vec::new());
+        let leaders = thread::scope(|s| {
+            let handles: Vec<_> = (0..N)
+                .map(|i| {
+                    let (barrier, times) = (&barrier, &times);
+                    s.spawn(move || {
+                        thread::sleep(Duration::from_millis(10 * i as u64));
+                        let arrived = Instant::now();
+                        let leader = barrier.wait();
+                        times.lock().unwrap().push((arrived, Instant::now()));
+                        leader
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .filter(|&leader| leader)
+                .count()
+        });
+        assert_eq!(leaders, 1);
+        let times = times.into_inner().unwrap();
+        let last_arrival = times.iter().map(|t| t.0).max().unwrap();
+        assert!(times.iter().all(|t| t.1 >= last_arrival));
+    }
+
+    #[test]
+    fn reusable_for_many_rounds() {
+        let barrier = SpinBarrier::new(3);
+        let round = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    for r in 0..50 {
+                        // Everybody is still in round `r`, until the leader moves on.
+                        assert_eq!(round.load(Ordering::Relaxed), r);
+                        if barrier.wait() {
+                            round.fetch_add(1, Ordering::Relaxed);
+                        }
+                        barrier.wait();
+                    }
+                });
+            }
+        });
+        assert_eq!(round.into_inner(), 50);
+    }
+}
@@ -0,0 +1,54 @@
+//! Shared by `benches/locks.rs` and the test below, not part of the API.
+
+use core::hint::black_box;
+
+use crate::SpinLock;
+
+/// `iters` lock / bump / unlock cycles on a lock nobody else touches, i.e.
+/// only the fast path: one successful CAS in, one `Release` store out (plus
+/// the debug-build bookkeeping). Returns the counter so the work can't be
+/// optimized away.
+///
+/// In a release build that is on the order of 10ns per cycle on a current
+/// x86_64 core, most of it the CAS; a debug build is around 10x that.
+pub fn uncontended_cycles(lock: &SpinLock<u64>, iters: u64) -> u64 {
+    for _ in 0..iters {
+        *black_box(lock).lock_with_cas() += 1;
+    }
+    *lock.lock_with_cas()
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    /// Not a benchmark, just a tripwire for something going very wrong on
+    /// the fast path, like a syscall or a contended atomic sneaking in. The
+    /// fastest of several batches counts, so other tests running at the
+    /// same time don't make it flaky.
+    #[test]
+    fn uncontended_fast_path_stays_fast() {
+        const BATCH: u64 = 10_000;
+        let limit = if cfg!(debug_assertions) {
+            Duration::from_micros(2)
+        } else {
+            Duration::from_nanos(200)
+        };
+        let x = SpinLock::new(0);
+        let best = (0..10)
+            .map(|_| {
+                let start = Instant::now();
+                uncontended_cycles(&x, BATCH);
+                start.elapsed() / BATCH as u32
+            })
+            .min()
+            .unwrap();
+        assert_eq!(x.into_inner(), 10 * BATCH);
+        assert!(
+            best < limit,
+            "uncontended cycle took {best:?}, limit {limit:?}"
+        );
+    }
+}
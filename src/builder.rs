@@ -0,0 +1,73 @@
+use crate::SpinLock;
+
+/// Builds a `SpinLock` with non-default tuning.
+///
+/// ```
+/// use my_spin_lock::SpinLockBuilder;
+///
+/// // Give up spinning early, the holder is expected to run for a while.
+/// let lock = SpinLockBuilder::new(0).max_spins(8).build();
+/// # #[cfg(feature = "std")] {
+/// *lock.lock_adaptive() += 1;
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SpinLockBuilder<T> {
+    value: T,
+    max_spins: usize,
+}
+
+impl<T> SpinLockBuilder<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            value,
+            max_spins: SpinLock::<T>::ADAPTIVE_SPINS,
+        }
+    }
+
+    /// How many times `lock_adaptive` spins before it starts yielding.
+    /// `0` yields right after the first failed attempt. Without `std` there
+    /// is no `lock_adaptive`, and this does nothing.
+    pub fn max_spins(mut self, max_spins: usize) -> Self {
+        self.max_spins = max_spins;
+        self
+    }
+
+    pub fn build(self) -> SpinLock<T> {
+        #[cfg(feature = "std")]
+        {
+            let mut lock = SpinLock::new(self.value);
+            lock.max_spins = self.max_spins;
+            lock
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            let _ = self.max_spins;
+            SpinLock::new(self.value)
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn small_spin_budget_under_contention() {
+        let x = SpinLockBuilder::new(0).max_spins(2).build();
+        assert_eq!(x.max_spins, 2);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *x.lock_adaptive() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(x.into_inner(), 4000);
+    }
+}
@@ -0,0 +1,130 @@
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// A `SpinLock` whose state is a plain byte: `UNLOCKED` (0) or `LOCKED` (1).
+///
+/// Same size as the `AtomicBool` in `SpinLock`, but the byte is visible via
+/// `raw_state()`, for code that mirrors or packs the lock flag together with
+/// other flags. The other 254 values are never used by the lock.
+pub struct SpinLockU8<T> {
+    state: AtomicU8,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for SpinLockU8<T> where T: Send {}
+
+impl<T> SpinLockU8<T> {
+    pub const UNLOCKED: u8 = 0;
+    pub const LOCKED: u8 = 1;
+
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU8::new(Self::UNLOCKED),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn lock(&self) -> SpinLockU8Guard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn try_lock(&self) -> Option<SpinLockU8Guard<'_, T>> {
+        self.state
+            .compare_exchange(
+                Self::UNLOCKED,
+                Self::LOCKED,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .ok()
+            .map(|_| SpinLockU8Guard {
+                lock: self,
+                _not_send: PhantomData,
+            })
+    }
+
+    /// The current state byte. A snapshot like `SpinLock::is_locked`.
+    pub fn raw_state(&self) -> u8 {
+        self.state.load(Ordering::Relaxed)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+/// Holds a `SpinLockU8`, made by `lock` or `try_lock`.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct SpinLockU8Guard<'a, T> {
+    lock: &'a SpinLockU8<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Sync> Sync for SpinLockU8Guard<'_, T> {}
+
+impl<T> Deref for SpinLockU8Guard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for SpinLockU8Guard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for SpinLockU8Guard<'_, T> {
+    fn drop(&mut self) {
+        self.lock
+            .state
+            .store(SpinLockU8::<T>::UNLOCKED, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn raw_state_follows_lock() {
+        let x = SpinLockU8::new(0);
+        assert_eq!(x.raw_state(), 0);
+        let mut g = x.lock();
+        assert_eq!(x.raw_state(), 1);
+        assert!(x.try_lock().is_none());
+        *g += 1;
+        drop(g);
+        assert_eq!(x.raw_state(), 0);
+        assert_eq!(core::mem::size_of::<AtomicU8>(), 1);
+    }
+
+    #[test]
+    fn counts_under_contention() {
+        let x = SpinLockU8::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *x.lock() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(x.into_inner(), 4000);
+    }
+}
@@ -0,0 +1,132 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{Backoff, Guard, SpinLock};
+
+/// A condition variable for `SpinLock`, spinning instead of blocking in the OS.
+///
+/// `wait()` remembers the current generation, releases the lock, spins (with
+/// `Backoff`) until a `notify_*` bumps the generation and then locks again.
+/// The generation is read while the lock is still held, so a notification
+/// sent after the waiter's condition check can't be missed, as long as the
+/// notifier changes the condition under the same lock.
+///
+/// Like `std::sync::Condvar`, always wait in a loop re-checking the condition:
+/// there is no way to wake just one spinner, so `notify_one` wakes them all
+/// and only one of them may find what it was waiting for.
+///
+/// ```
+/// use my_spin_lock::{SpinCondvar, SpinLock};
+///
+/// let ready = SpinLock::new(false);
+/// let cv = SpinCondvar::new();
+/// std::thread::scope(|s| {
+///     s.spawn(|| {
+///         *ready.lock_with_cas() = true;
+///         cv.notify_one();
+///     });
+///     let mut g = ready.lock_with_cas();
+///     while !*g {
+///         g = cv.wait(g);
+///     }
+/// });
+/// ```
+#[derive(Debug, Default)]
+pub struct SpinCondvar {
+    generation: AtomicUsize,
+}
+
+impl SpinCondvar {
+    pub const fn new() -> Self {
+        Self {
+            generation: AtomicUsize::new(0),
+        }
+    }
+
+    /// Releases the lock behind `guard`, waits for a notification and takes
+    /// the lock again before returning.
+    pub fn wait<'a, T: ?Sized, const B: usize>(&self, guard: Guard<'a, T, B>) -> Guard<'a, T, B> {
+        let lock = guard.lock;
+        let generation = self.generation.load(Ordering::Relaxed);
+        drop(guard);
+        let mut backoff = Backoff::new();
+        // The lock hands the values over, the counter only has to change.
+        while self.generation.load(Ordering::Relaxed) == generation {
+            backoff.snooze();
+        }
+        lock.lock_with_cas()
+    }
+
+    /// Wakes the waiters; see the type docs for why that is all of them.
+    pub fn notify_one(&self) {
+        self.notify_all();
+    }
+
+    pub fn notify_all(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl<T: ?Sized, const B: usize> SpinLock<T, B> {
+    /// Runs `f` under the lock, releases it and then notifies `condvar`:
+    /// the change-then-notify half of the pattern in the `SpinCondvar` docs
+    /// as one call. Since the change happens under the lock, a waiter can't
+    /// check the condition in between and miss the notification; since the
+    /// notification comes after the release, woken waiters find the lock free.
+    /// The waiters are notified even if `f` panics.
+    pub fn update_and_notify(&self, condvar: &SpinCondvar, f: impl FnOnce(&mut T)) {
+        let mut guard = self.lock_with_on_unlock(|| condvar.notify_all());
+        f(&mut guard);
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::{collections::VecDeque, thread};
+
+    use super::*;
+
+    #[test]
+    fn consumer_waits_for_producer() {
+        let queue = SpinLock::new((VecDeque::new(), false));
+        let cv = SpinCondvar::new();
+        let mut got = Vec::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..100 {
+                    queue.lock_with_cas().0.push_back(i);
+                    cv.notify_one();
+                }
+                queue.lock_with_cas().1 = true;
+                cv.notify_all();
+            });
+            let mut g = queue.lock_with_cas();
+            loop {
+                while let Some(i) = g.0.pop_front() {
+                    got.push(i);
+                }
+                if g.1 {
+                    break;
+                }
+                g = cv.wait(g);
+            }
+        });
+        assert_eq!(got, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn update_and_notify_wakes_consumer() {
+        let slot = SpinLock::new(None);
+        let cv = SpinCondvar::new();
+        for round in 0..50 {
+            thread::scope(|s| {
+                s.spawn(|| slot.update_and_notify(&cv, |v| *v = Some(round)));
+                let mut g = slot.lock_with_cas();
+                while g.is_none() {
+                    g = cv.wait(g);
+                }
+                assert_eq!(g.take(), Some(round));
+            });
+        }
+        assert!(!slot.is_locked());
+    }
+}
@@ -0,0 +1,61 @@
+use crate::SpinLock;
+
+impl<T: ?Sized, const B: usize> SpinLock<T, B> {
+    /// For locks shared with interrupt handlers on bare metal: runs `f` on the
+    /// value inside a critical section (on a single core, interrupts are
+    /// disabled), locking with `lock_with_cas` once in it and unlocking before
+    /// leaving it.
+    ///
+    /// With a plain `lock_*`, an interrupt arriving while the lock is held
+    /// and whose handler locks it too spins forever: the holder can't run
+    /// again until the handler returns. Inside the critical section that
+    /// interrupt has to wait instead.
+    ///
+    /// A closure rather than a guard: `critical-section` needs every acquire
+    /// released exactly once and in nesting order, which a guard that can be
+    /// forgotten or dropped out of order couldn't promise.
+    ///
+    /// The `critical-section` crate needs an implementation for the target,
+    /// usually from the HAL or the `cortex-m` crate's `critical-section-single-core`.
+    pub fn with_lock_cs<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        critical_section::with(|_| f(&mut self.lock_with_cas()))
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    static LOCK: SpinLock<u32> = SpinLock::new(0);
+    // (entering?, was `LOCK` held at that moment?) per critical section call.
+    static EVENTS: Mutex<Vec<(bool, bool)>> = Mutex::new(Vec::new());
+
+    /// Stands in for disabling interrupts: just records when it's called.
+    struct HostShim;
+    critical_section::set_impl!(HostShim);
+
+    unsafe impl critical_section::Impl for HostShim {
+        unsafe fn acquire() -> critical_section::RawRestoreState {
+            EVENTS.lock().unwrap().push((true, LOCK.is_locked()));
+        }
+
+        unsafe fn release(_: critical_section::RawRestoreState) {
+            EVENTS.lock().unwrap().push((false, LOCK.is_locked()));
+        }
+    }
+
+    #[test]
+    fn critical_section_brackets_the_lock() {
+        let got = LOCK.with_lock_cs(|v| {
+            *v += 1;
+            assert_eq!(*EVENTS.lock().unwrap(), [(true, false)]);
+            *v
+        });
+        assert_eq!(got, 1);
+        // Entered before locking, left after unlocking.
+        assert_eq!(*EVENTS.lock().unwrap(), [(true, false), (false, false)]);
+        assert_eq!(*LOCK.try_lock().unwrap(), 1);
+    }
+}
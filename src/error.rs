@@ -0,0 +1,162 @@
+use core::{error, fmt};
+
+/// Returned by the `_checked` lock methods when a previous holder panicked
+/// while holding the lock, so the protected data may be half-updated.
+///
+/// Like `std::sync::PoisonError` it still carries the guard: the lock is held,
+/// and the caller can decide whether to trust the data via `into_inner()`.
+pub struct PoisonError<G> {
+    guard: G,
+}
+
+/// Result of a lock method that reports poisoning.
+pub type LockResult<G> = Result<G, PoisonError<G>>;
+
+impl<G> PoisonError<G> {
+    pub fn new(guard: G) -> Self {
+        Self { guard }
+    }
+
+    /// Ignores the poison and takes the guard anyway.
+    pub fn into_inner(self) -> G {
+        self.guard
+    }
+
+    pub fn get_ref(&self) -> &G {
+        &self.guard
+    }
+
+    pub fn get_mut(&mut self) -> &mut G {
+        &mut self.guard
+    }
+}
+
+/// Doesn't require `G: Debug`, the guard isn't interesting to print anyway.
+impl<G> fmt::Debug for PoisonError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PoisonError").finish_non_exhaustive()
+    }
+}
+
+impl<G> fmt::Display for PoisonError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("spinlock poisoned by a panicking thread")
+    }
+}
+
+impl<G> error::Error for PoisonError<G> {}
+
+/// Returned by `SpinLock::try_unlock` when unlocking makes no sense.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UnlockError {
+    /// The lock wasn't held, so there was nothing to release.
+    NotLocked,
+}
+
+impl fmt::Display for UnlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotLocked => f.write_str("tried to unlock a spinlock that is not locked"),
+        }
+    }
+}
+
+impl error::Error for UnlockError {}
+
+/// Everything `SpinLock::lock_checked` can fail with, so callers can handle
+/// each case instead of unwinding.
+#[non_exhaustive]
+pub enum LockError<G> {
+    /// A previous holder panicked; the lock is held, see `PoisonError`.
+    Poisoned(PoisonError<G>),
+    /// Locking would never return: this thread already holds the lock, or
+    /// it would break a `with_level` hierarchy. Only detected in debug builds
+    /// with `std`, like the panicking checks of the other lock methods.
+    WouldDeadlock,
+}
+
+impl<G> From<PoisonError<G>> for LockError<G> {
+    fn from(err: PoisonError<G>) -> Self {
+        Self::Poisoned(err)
+    }
+}
+
+impl<G> fmt::Debug for LockError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Poisoned(err) => f.debug_tuple("Poisoned").field(err).finish(),
+            Self::WouldDeadlock => f.write_str("WouldDeadlock"),
+        }
+    }
+}
+
+impl<G> fmt::Display for LockError<G> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Poisoned(err) => fmt::Display::fmt(err, f),
+            Self::WouldDeadlock => f.write_str("locking the spinlock would deadlock"),
+        }
+    }
+}
+
+/// No `source()`: it would have to be `'static`, which the guard inside a
+/// `Poisoned` usually isn't. `Display` prints the poison message instead.
+impl<G> error::Error for LockError<G> {}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::{error::Error, thread};
+
+    use super::*;
+    use crate::SpinLock;
+
+    #[test]
+    fn display_messages() {
+        let poisoned = PoisonError::new(());
+        assert_eq!(
+            poisoned.to_string(),
+            "spinlock poisoned by a panicking thread"
+        );
+        assert_eq!(
+            LockError::from(PoisonError::new(())).to_string(),
+            "spinlock poisoned by a panicking thread"
+        );
+        assert_eq!(
+            LockError::<()>::WouldDeadlock.to_string(),
+            "locking the spinlock would deadlock"
+        );
+        assert_eq!(
+            UnlockError::NotLocked.to_string(),
+            "tried to unlock a spinlock that is not locked"
+        );
+        assert_eq!(format!("{poisoned:?}"), "PoisonError { .. }");
+    }
+
+    // Without `std` nothing gets poisoned.
+    #[cfg(feature = "std")]
+    #[test]
+    fn errors_propagate_with_question_mark() {
+        fn bump(lock: &SpinLock<i32>) -> Result<(), Box<dyn Error + '_>> {
+            *lock.lock_checked()? += 1;
+            Ok(())
+        }
+        fn is_error<E: Error>(_: &E) {}
+
+        let lock = SpinLock::new(0);
+        bump(&lock).unwrap();
+        let _ = thread::scope(|s| {
+            s.spawn(|| {
+                let _g = lock.lock_with_cas();
+                panic!("poison it");
+            })
+            .join()
+        });
+        let err = bump(&lock).unwrap_err();
+        assert_eq!(err.to_string(), "spinlock poisoned by a panicking thread");
+        assert!(err.source().is_none());
+        is_error(&UnlockError::NotLocked);
+        is_error(&PoisonError::new(()));
+        is_error(&LockError::<()>::WouldDeadlock);
+    }
+}
@@ -0,0 +1,245 @@
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+/// How many times `lock()` retries before it goes to sleep.
+const SPINS: usize = 100;
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+// Locked, and somebody may be asleep waiting for it.
+const CONTENDED: u32 = 2;
+
+/// A lock that spins briefly and then sleeps in the kernel until released.
+///
+/// On Linux a waiter blocks on the lock word itself with `futex(2)` and the
+/// release wakes one of them, so a long hold costs the waiters no CPU at all.
+/// The uncontended lock and unlock are a single atomic each, no syscall; only
+/// a release that saw a sleeper makes one. Elsewhere waiters `thread::park()`
+/// on a list of sleepers kept next to the word, like `SpinLock::lock_parking`.
+///
+/// The word is an `AtomicU32` (what `futex` works on), and its three states
+/// follow Drepper's "Futexes Are Tricky": `UNLOCKED`, `LOCKED`, and
+/// `CONTENDED` for "locked and release has to wake someone".
+pub struct FutexSpinLock<T> {
+    state: AtomicU32,
+    waiters: sys::Waiters,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for FutexSpinLock<T> where T: Send {}
+
+impl<T> FutexSpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+            waiters: sys::Waiters::new(),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn lock(&self) -> FutexGuard<'_, T> {
+        if let Some(guard) = self.try_lock() {
+            return guard;
+        }
+        for _ in 0..SPINS {
+            core::hint::spin_loop();
+            if self.state.load(Ordering::Relaxed) == UNLOCKED {
+                if let Some(guard) = self.try_lock() {
+                    return guard;
+                }
+            }
+        }
+        // From here on we may sleep, so whoever unlocks has to wake us. We
+        // can't tell whether other sleepers remain, so even once we've got the
+        // lock it stays `CONTENDED`; at worst that costs one needless wake.
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            self.waiters.wait(&self.state, CONTENDED);
+        }
+        FutexGuard {
+            lock: self,
+            _not_send: PhantomData,
+        }
+    }
+
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn try_lock(&self) -> Option<FutexGuard<'_, T>> {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| FutexGuard {
+                lock: self,
+                _not_send: PhantomData,
+            })
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+/// Holds a `FutexSpinLock`, made by `lock` or `try_lock`.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct FutexGuard<'a, T> {
+    lock: &'a FutexSpinLock<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Sync> Sync for FutexGuard<'_, T> {}
+
+impl<T> Deref for FutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for FutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for FutexGuard<'_, T> {
+    fn drop(&mut self) {
+        if self.lock.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            self.lock.waiters.wake_one(&self.lock.state);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use core::{ptr, sync::atomic::AtomicU32};
+
+    /// The kernel keeps the sleepers, so there is nothing to store.
+    pub(super) struct Waiters;
+
+    impl Waiters {
+        pub(super) const fn new() -> Self {
+            Self
+        }
+
+        /// Sleeps while `*word == expected`. Returns on a wake, a signal, or
+        /// straight away if the value already changed; the caller re-checks.
+        pub(super) fn wait(&self, word: &AtomicU32, expected: u32) {
+            unsafe {
+                libc::syscall(
+                    libc::SYS_futex,
+                    word.as_ptr(),
+                    libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+                    expected,
+                    ptr::null::<libc::timespec>(),
+                );
+            }
+        }
+
+        pub(super) fn wake_one(&self, word: &AtomicU32) {
+            unsafe {
+                libc::syscall(
+                    libc::SYS_futex,
+                    word.as_ptr(),
+                    libc::FUTEX_WAKE | libc::FUTEX_PRIVATE_FLAG,
+                    1,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    use crate::park::WaitList;
+
+    /// No futex here, so the sleepers park on a list of their own.
+    pub(super) struct Waiters(WaitList);
+
+    impl Waiters {
+        pub(super) const fn new() -> Self {
+            Self(WaitList::new())
+        }
+
+        /// Same contract as `futex(FUTEX_WAIT)`: parks while
+        /// `*word == expected`, and the caller re-checks.
+        pub(super) fn wait(&self, word: &AtomicU32, expected: u32) {
+            self.0
+                .wait_until(|| word.load(Ordering::Relaxed) != expected);
+        }
+
+        pub(super) fn wake_one(&self, _word: &AtomicU32) {
+            self.0.wake_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+
+    #[test]
+    fn counts_under_contention() {
+        let x = FutexSpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *x.lock() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(x.into_inner(), 4000);
+    }
+
+    #[test]
+    fn try_lock_fails_while_held() {
+        let x = FutexSpinLock::new(());
+        let g = x.lock();
+        assert!(x.try_lock().is_none());
+        drop(g);
+        assert!(x.try_lock().is_some());
+    }
+
+    /// CPU time the calling thread has used so far.
+    #[cfg(target_os = "linux")]
+    fn thread_cpu_time() -> Duration {
+        let mut usage = unsafe { core::mem::zeroed::<libc::rusage>() };
+        assert_eq!(
+            unsafe { libc::getrusage(libc::RUSAGE_THREAD, &mut usage) },
+            0
+        );
+        let tv = |t: libc::timeval| Duration::new(t.tv_sec as u64, t.tv_usec as u32 * 1000);
+        tv(usage.ru_utime) + tv(usage.ru_stime)
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn waiter_sleeps_through_a_long_hold() {
+        let x = FutexSpinLock::new(0);
+        let g = x.lock();
+        let cpu = thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                let before = thread_cpu_time();
+                *x.lock() += 1;
+                thread_cpu_time() - before
+            });
+            thread::sleep(Duration::from_millis(200));
+            drop(g);
+            waiter.join().unwrap()
+        });
+        assert_eq!(x.into_inner(), 1);
+        // A spinning waiter would have burnt most of the 200ms.
+        assert!(
+            cpu < Duration::from_millis(50),
+            "waiter used {cpu:?} of CPU"
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A spin lock whose single `AtomicUsize` holds both the lock bit and a
+/// generation counter: `state = generation * 2 + locked`.
+///
+/// Locking CASes an even state to `state + 1`, unlocking adds 1 again, which
+/// clears the lock bit and carries into the generation in one `fetch_add`.
+/// So every release bumps `generation()`, which a waiter can watch to notice
+/// that the lock changed hands while it wasn't looking, without an extra
+/// atomic. The counter wraps around after `usize::MAX / 2` releases.
+pub struct GenerationSpinLock<T> {
+    state: AtomicUsize,
+    value: UnsafeCell<T>,
+}
+
+const LOCKED: usize = 1;
+
+unsafe impl<T> Sync for GenerationSpinLock<T> where T: Send {}
+
+impl<T> GenerationSpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn lock(&self) -> GenerationGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn try_lock(&self) -> Option<GenerationGuard<'_, T>> {
+        let state = self.state.load(Ordering::Relaxed);
+        if state & LOCKED != 0 {
+            return None;
+        }
+        self.state
+            .compare_exchange(state, state | LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| GenerationGuard {
+                lock: self,
+                _not_send: PhantomData,
+            })
+    }
+
+    /// How many times the lock has been released so far (wrapping).
+    pub fn generation(&self) -> usize {
+        self.state.load(Ordering::Relaxed) >> 1
+    }
+
+    /// Advisory only, see `SpinLock::is_locked`.
+    pub fn is_locked(&self) -> bool {
+        self.state.load(Ordering::Relaxed) & LOCKED != 0
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+/// Holds a `GenerationSpinLock`; dropping it bumps the generation.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct GenerationGuard<'a, T> {
+    lock: &'a GenerationSpinLock<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Sync> Sync for GenerationGuard<'_, T> {}
+
+impl<T> Deref for GenerationGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for GenerationGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for GenerationGuard<'_, T> {
+    fn drop(&mut self) {
+        // Odd -> even: unlocked, next generation.
+        self.lock.state.fetch_add(1, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn generation_bumps_on_unlock() {
+        let x = GenerationSpinLock::new(0);
+        assert_eq!(x.generation(), 0);
+        let g = x.lock();
+        assert!(x.is_locked());
+        assert!(x.try_lock().is_none());
+        assert_eq!(x.generation(), 0);
+        drop(g);
+        assert!(!x.is_locked());
+        assert_eq!(x.generation(), 1);
+        *x.try_lock().unwrap() += 1;
+        assert_eq!(x.generation(), 2);
+    }
+
+    #[test]
+    fn counts_under_contention() {
+        let x = GenerationSpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *x.lock() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(x.generation(), 4000);
+        assert_eq!(x.into_inner(), 4000);
+    }
+}
@@ -0,0 +1,45 @@
+//! Debug-build bookkeeping for `SpinLock::with_level`: which levelled locks
+//! the current thread holds, innermost last.
+
+use std::{cell::RefCell, vec::Vec};
+
+std::thread_local! {
+    // `(level, lock address)`, the address so two locks can't be mixed up.
+    static HELD: RefCell<Vec<(u32, usize)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Panics unless `level` is strictly below every level this thread holds.
+pub(crate) fn check(level: u32) {
+    if let Some(lowest) = lowest() {
+        assert!(
+            level < lowest,
+            "lock ordering violation: locking level {level} while holding level {lowest}"
+        );
+    }
+}
+
+/// `check` without the panic.
+pub(crate) fn allows(level: u32) -> bool {
+    lowest().is_none_or(|lowest| level < lowest)
+}
+
+// Not just the innermost one: `try_lock` never checks, so whatever it took
+// may sit on top of lower levels.
+fn lowest() -> Option<u32> {
+    HELD.with(|held| held.borrow().iter().map(|&(level, _)| level).min())
+}
+
+pub(crate) fn push(level: u32, lock: usize) {
+    HELD.with(|held| held.borrow_mut().push((level, lock)));
+}
+
+/// Guards may be dropped in any order, and an `ArcGuard` may be dropped on a
+/// thread that never pushed it, so this just removes the entry if it's there.
+pub(crate) fn pop(level: u32, lock: usize) {
+    HELD.with(|held| {
+        let mut held = held.borrow_mut();
+        if let Some(i) = held.iter().rposition(|&entry| entry == (level, lock)) {
+            held.remove(i);
+        }
+    });
+}
@@ -0,0 +1,105 @@
+use core::{cell::UnsafeCell, panic::Location};
+use std::{thread::ThreadId, vec::Vec};
+
+use crate::RawSpinLock;
+
+/// How many acquisitions `SpinLock::acquisition_history` remembers.
+pub const HISTORY_LEN: usize = 8;
+
+type Entry = (ThreadId, &'static Location<'static>);
+
+/// The last `HISTORY_LEN` acquisitions of one lock, for the `history`
+/// feature. Only written by whoever just took the lock, but read by anyone
+/// at any time, including while the lock is stuck, so it has a little lock
+/// of its own instead of relying on the `SpinLock`'s.
+pub(crate) struct History {
+    lock: RawSpinLock,
+    ring: UnsafeCell<Ring>,
+}
+
+struct Ring {
+    slots: [Option<Entry>; HISTORY_LEN],
+    // Where the next entry goes, which is also the oldest one once full.
+    next: usize,
+}
+
+impl History {
+    const_fn! {
+        pub(crate) const fn new() -> Self {
+            Self {
+                lock: RawSpinLock::new(),
+                ring: UnsafeCell::new(Ring {
+                    slots: [None; HISTORY_LEN],
+                    next: 0,
+                }),
+            }
+        }
+    }
+
+    pub(crate) fn record(&self, at: &'static Location<'static>) {
+        let id = std::thread::current().id();
+        self.with_ring(|ring| {
+            ring.slots[ring.next] = Some((id, at));
+            ring.next = (ring.next + 1) % HISTORY_LEN;
+        });
+    }
+
+    /// Oldest first.
+    pub(crate) fn entries(&self) -> Vec<Entry> {
+        self.with_ring(|ring| {
+            let (new, old) = ring.slots.split_at(ring.next);
+            old.iter().chain(new).flatten().copied().collect()
+        })
+    }
+
+    fn with_ring<R>(&self, f: impl FnOnce(&mut Ring) -> R) -> R {
+        self.lock.lock();
+        // Only ever touched under `self.lock`, and `f` can't panic.
+        let r = f(unsafe { &mut *self.ring.get() });
+        unsafe { self.lock.unlock() };
+        r
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::SpinLock;
+
+    #[test]
+    fn history_lists_recent_acquisitions_in_order() {
+        let x = SpinLock::new(0);
+        assert!(x.acquisition_history().is_empty());
+        let me = thread::current().id();
+        let (g, first) = (x.lock_with_cas(), line!());
+        drop(g);
+        let (other, second) = thread::scope(|s| {
+            s.spawn(|| {
+                let (g, line) = (x.try_lock(), line!());
+                drop(g);
+                (thread::current().id(), line)
+            })
+            .join()
+            .unwrap()
+        });
+        let (held, third) = (x.lock(), line!());
+        let history = x.acquisition_history();
+        let seen: Vec<_> = history.iter().map(|(id, at)| (*id, at.line())).collect();
+        assert_eq!(seen, [(me, first), (other, second), (me, third)]);
+        assert!(history.iter().all(|(_, at)| at.file() == file!()));
+        drop(held);
+
+        // Only the last `HISTORY_LEN` are kept.
+        let mut last = 0;
+        for _ in 0..HISTORY_LEN {
+            let g;
+            (g, last) = (x.lock_with_swap(), line!());
+            drop(g);
+        }
+        let history = x.acquisition_history();
+        assert_eq!(history.len(), HISTORY_LEN);
+        assert!(history.iter().all(|(_, at)| at.line() == last));
+    }
+}
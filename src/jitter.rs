@@ -0,0 +1,41 @@
+/// A xorshift32 generator, nowhere near good randomness but enough to keep
+/// contending threads from retrying in lockstep, and it needs no `std`.
+pub(crate) struct XorShift(u32);
+
+impl XorShift {
+    /// Seeded from the address of a stack variable, which differs between
+    /// threads (each has its own stack) and costs nothing to get.
+    pub(crate) fn for_this_thread() -> Self {
+        let local = 0u8;
+        let addr = &local as *const u8 as usize as u64;
+        // Fold the high half in, and never start at 0 (xorshift's fixpoint).
+        Self((addr ^ (addr >> 32)) as u32 | 1)
+    }
+
+    pub(crate) fn next(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+
+    /// A number in `0..n`, `n` must not be 0. Slightly biased, which is fine here.
+    pub(crate) fn below(&mut self, n: u32) -> u32 {
+        self.next() % n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stays_in_range_and_varies() {
+        let mut rng = XorShift::for_this_thread();
+        let draws: Vec<u32> = (0..100).map(|_| rng.below(8)).collect();
+        assert!(draws.iter().all(|&d| d < 8));
+        assert!(draws.iter().any(|&d| d != draws[0]));
+    }
+}
@@ -1,21 +1,320 @@
 //  实现一个我自己的自旋锁SpinLock
 #![allow(unused)]
 #![allow(dead_code)]
+//! The core lock only needs `core` (`UnsafeCell`, atomics and `spin_loop`),
+//! so the crate is `no_std` when the default `std` feature is turned off.
+//!
+//! Without `std` you lose:
+//! - `try_lock_for` / `try_lock_until`, which need `Instant`;
+//! - yielding to the OS in `Backoff` (it keeps spinning instead);
+//! - everything that needs threads or the heap: `lock_adaptive`,
+//!   `lock_yielding`, `new_autotuned`, `YieldRelax`, `lock_arc`,
+//!   `lock_owned`, `lock_pair_arc`, `try_unwrap`,
+//!   `upgrade_and_lock`, `into_raw` / `from_raw`, `try_lock_all`,
+//!   `ReentrantSpinLock`, `SpinStack`, `locked_vec` and the
+//!   `SpinLock<Vec<T>>` shortcuts;
+//! - the debug-build self-deadlock and `with_level` checks;
+//! - poisoning: a lock is never marked poisoned, since `core` can't tell
+//!   whether we are unwinding; nor can it catch a panic for `with_lock_catch`.
+// The tests spawn threads, so they always link `std`.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
-use std::{
+#[cfg(all(feature = "default-cas", feature = "default-swap"))]
+compile_error!(
+    "features `default-cas` and `default-swap` are mutually exclusive; \
+     `default-cas` is on by default, so use `default-features = false` with `default-swap`"
+);
+
+/// loom's atomics can't be created in a `const` context,
+/// so under `cfg(loom)` the constructors wrapped in this lose their `const`.
+macro_rules! const_fn {
+    ($(#[$attr:meta])* $vis:vis const fn $($rest:tt)*) => {
+        #[cfg(not(loom))]
+        $(#[$attr])* $vis const fn $($rest)*
+        #[cfg(loom)]
+        $(#[$attr])* $vis fn $($rest)*
+    };
+}
+
+mod accessors;
+#[cfg(feature = "std")]
+mod autotune;
+mod backoff;
+mod barrier;
+#[doc(hidden)]
+pub mod bench;
+mod builder;
+mod byte;
+mod condvar;
+#[cfg(feature = "critical-section")]
+mod cs;
+mod error;
+#[cfg(feature = "futex")]
+mod futex;
+mod generation;
+#[cfg(all(debug_assertions, feature = "std"))]
+mod hierarchy;
+#[cfg(feature = "history")]
+mod history;
+mod jitter;
+mod lock_guard;
+mod mcs;
+mod once;
+mod ordered;
+#[cfg(feature = "std")]
+mod owned;
+mod padded;
+// `FutexSpinLock` parks on a `WaitList` where there is no futex.
+#[cfg(any(feature = "park", all(feature = "futex", not(target_os = "linux"))))]
+mod park;
+mod raw;
+#[cfg(feature = "std")]
+mod reentrant;
+mod relax;
+mod ring;
+mod rwlock;
+mod semaphore;
+#[cfg(feature = "serde")]
+mod serde_impl;
+mod shard;
+#[cfg(feature = "smart")]
+mod smart;
+#[cfg(feature = "std")]
+mod stack;
+#[cfg(feature = "stats")]
+mod stats;
+mod ticket;
+
+pub use backoff::{cpu_relax, Backoff};
+pub use barrier::SpinBarrier;
+pub use builder::SpinLockBuilder;
+pub use byte::{SpinLockU8, SpinLockU8Guard};
+pub use condvar::SpinCondvar;
+pub use error::{LockError, LockResult, PoisonError, UnlockError};
+#[cfg(feature = "futex")]
+pub use futex::{FutexGuard, FutexSpinLock};
+pub use generation::{GenerationGuard, GenerationSpinLock};
+#[cfg(feature = "history")]
+pub use history::HISTORY_LEN;
+pub use lock_guard::LockGuard;
+pub use mcs::{McsGuard, McsNode, McsSpinLock};
+pub use once::{SpinOnce, SpinOnceCell};
+pub use ordered::{lock_two, LockAll};
+#[cfg(feature = "std")]
+pub use owned::{lock_pair_arc, try_unwrap, upgrade_and_lock, ArcGuard, OwnedGuard};
+pub use padded::PaddedSpinLock;
+pub use raw::{is_lock_free, RawSpinLock};
+#[cfg(feature = "std")]
+pub use reentrant::{ReentrantGuard, ReentrantSpinLock};
+#[cfg(feature = "std")]
+pub use relax::YieldRelax;
+pub use relax::{NoopRelax, Relax, SpinRelax};
+pub use ring::SpinRingBuffer;
+pub use rwlock::{ReadGuard, RwSpinLock, UpgradableReadGuard, WriteGuard};
+pub use semaphore::SpinSemaphore;
+#[cfg(feature = "std")]
+pub use shard::try_lock_all;
+pub use shard::try_lock_any;
+#[cfg(feature = "smart")]
+pub use smart::SmartGuard;
+#[cfg(feature = "std")]
+pub use stack::SpinStack;
+#[cfg(feature = "stats")]
+pub use stats::LockStats;
+pub use ticket::{TicketGuard, TicketSpinLock};
+
+use core::{
     cell::UnsafeCell,
-    error,
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    mem::ManuallyDrop,
     ops::{Deref, DerefMut},
-    sync::atomic::AtomicBool,
+    panic::Location,
+    sync::atomic::Ordering,
 };
+// Under `--cfg loom` the lock state is built on loom's model-checked atomics,
+// see `tests/loom.rs`.
+#[cfg(not(loom))]
+use core::{hint::spin_loop, sync::atomic::AtomicBool};
+#[cfg(loom)]
+use loom::{hint::spin_loop, sync::atomic::AtomicBool};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// A number that is unique among the currently running threads: the address
+/// of a thread local. Never 0, so 0 can mean "nobody".
+#[cfg(feature = "std")]
+fn current_thread_id() -> usize {
+    std::thread_local!(static ID: u8 = const { 0 });
+    ID.with(|id| id as *const u8 as usize)
+}
+
+// This thread's spins on locks with a `with_contention_threshold` since it
+// last acquired one of them or gave up waiting for one.
+#[cfg(all(feature = "tracing", feature = "std"))]
+std::thread_local!(static CONTENDED_SPINS: core::cell::Cell<u64> = const { core::cell::Cell::new(0) });
 
 /// 'a保证了Guard不会比SpinLock生命周期长。
-pub struct Guard<'a, T> {
-    lock: &'a SpinLock<T>,
+///
+/// A guard stands for "this thread holds the lock", so it is `!Send`:
+/// moving it to another thread would let that thread release a lock it never
+/// acquired. Sharing `&Guard` is fine though, it only gives out `&T`, so the
+/// guard is `Sync` whenever `T: Sync`.
+///
+/// ```compile_fail
+/// use my_spin_lock::SpinLock;
+///
+/// let lock = SpinLock::new(0);
+/// let guard = lock.lock_with_cas();
+/// std::thread::scope(|s| {
+///     // A guard can't leave the thread that locked it.
+///     s.spawn(move || drop(guard));
+/// });
+/// ```
+///
+/// Locking as a bare statement drops the guard on the spot, so the guard
+/// and the methods returning it are `#[must_use]`:
+///
+/// ```compile_fail
+/// #![deny(unused_must_use)]
+/// let lock = my_spin_lock::SpinLock::new(0);
+/// lock.lock_with_cas(); // error: unused `Guard` that must be used
+/// ```
+///
+/// `let _ = lock.lock_with_cas();` silences that and unlocks immediately as
+/// well; bind it to a named variable (`let _guard = ...`) instead.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct Guard<'a, T: ?Sized, const B: usize = 0> {
+    lock: &'a SpinLock<T, B>,
+    // Raw pointers are `!Send`, which makes the guard `!Send` as well.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<'a, T: ?Sized, const B: usize> Guard<'a, T, B> {
+    /// Caller must have just acquired `lock`.
+    #[track_caller]
+    fn new(lock: &'a SpinLock<T, B>) -> Self {
+        lock.acquired();
+        Self {
+            lock,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Narrows the guard down to a part of the protected value, e.g. one field.
+    /// The lock stays held until the returned `MappedGuard` is dropped.
+    ///
+    /// This is an associated function (`Guard::map(guard, ..)`) rather than a
+    /// method, so it can't shadow a `map` method on `T` through `Deref`.
+    pub fn map<U: ?Sized>(
+        mut guard: Self,
+        f: impl FnOnce(&mut T) -> &mut U,
+    ) -> MappedGuard<'a, T, U, B> {
+        let value: *mut U = f(&mut *guard);
+        MappedGuard { guard, value }
+    }
+
+    /// Like `map`, but `f` may decline by returning `None`, in which case
+    /// the untouched guard comes back in `Err` and the lock is still held.
+    pub fn try_map<U: ?Sized>(
+        mut guard: Self,
+        f: impl FnOnce(&mut T) -> Option<&mut U>,
+    ) -> Result<MappedGuard<'a, T, U, B>, Self> {
+        match f(&mut *guard).map(|v| v as *mut U) {
+            Some(value) => Ok(MappedGuard { guard, value }),
+            None => Err(guard),
+        }
+    }
+
+    /// The value, spelled out instead of going through `Deref`. An associated
+    /// function (`Guard::get(&guard)`) like `map`: as a method it would hide
+    /// `T`'s own `get`, e.g. `guard.get(0)` on a `Vec`.
+    pub fn get(guard: &Self) -> &T {
+        guard
+    }
+
+    /// `get` for `&mut T`, the explicit form of `DerefMut`.
+    pub fn get_mut(guard: &mut Self) -> &mut T {
+        guard
+    }
+
+    /// The lock this guard belongs to. An associated function like `map`, so
+    /// it can't shadow a method of `T`.
+    pub fn spinlock(guard: &Self) -> &'a SpinLock<T, B> {
+        guard.lock
+    }
+
+    /// Proof that the lock is held, for code that is handed the `&SpinLock`
+    /// again further down (a callback, a recursive call) and would deadlock
+    /// locking it a second time: `SpinLock::reenter` trades the token for
+    /// `&T`. The token borrows the guard, so while it exists the guard can't
+    /// hand out `&mut T`, nor be dropped:
+    ///
+    /// ```compile_fail
+    /// use my_spin_lock::{Guard, SpinLock};
+    /// let lock = SpinLock::new(0);
+    /// let mut guard = lock.lock_with_cas();
+    /// let token = Guard::token(&guard);
+    /// let seen = lock.reenter(&token);
+    /// *guard += 1; // error: `guard` is already borrowed by `token`
+    /// assert_eq!(*seen, 0);
+    /// ```
+    pub fn token(guard: &Self) -> LockToken<'_, T, B> {
+        LockToken { guard }
+    }
+
+    /// Gives up the guard but keeps the lock held for the rest of `'a`,
+    /// returning the `&mut T` it protected. Handy for set-up-once data that
+    /// no one else should ever lock again.
+    ///
+    /// The lock is never released on its own; if it must become usable again
+    /// the caller is responsible for calling `force_unlock` once the
+    /// reference is no longer used.
+    pub fn leak(guard: Self) -> &'a mut T {
+        let lock = guard.lock;
+        core::mem::forget(guard);
+        unsafe { &mut *lock.value.get() }
+    }
+
+    /// Turns the guard into one that does *not* unlock when dropped, for
+    /// handing the held lock up or across scopes that shouldn't release it
+    /// by accident. `DisarmedGuard::rearm` makes it a normal guard again.
+    pub fn disarm(guard: Self) -> DisarmedGuard<'a, T, B> {
+        DisarmedGuard {
+            guard: ManuallyDrop::new(guard),
+        }
+    }
+}
+
+impl<A, C, const B: usize> Guard<'_, (A, C), B> {
+    /// Both halves of a locked pair as separate `&mut`s, e.g. to move
+    /// something from one to the other. Same as `let (a, c) = &mut *guard;`,
+    /// for when that's awkward to write inline. Associated function like `map`.
+    pub fn fields(guard: &mut Self) -> (&mut A, &mut C) {
+        let (a, c) = &mut **guard;
+        (a, c)
+    }
 }
 
+/// The guard only hands out `&T` through shared references, which is exactly
+/// what `T: Sync` allows. (`PhantomData<*const ()>` would otherwise make it `!Sync` too.)
+/// Mutation needs `&mut Guard`, which can't be shared, and the release
+/// happens in `drop`, which only the owning thread runs. Without `T: Sync`,
+/// `&Guard` stays on its thread:
+///
+/// ```compile_fail
+/// use std::cell::Cell;
+/// let lock = my_spin_lock::SpinLock::new(Cell::new(0));
+/// let guard = lock.lock_with_cas();
+/// std::thread::scope(|s| {
+///     s.spawn(|| guard.set(1)); // error: `Cell<i32>` cannot be shared between threads
+/// });
+/// ```
+unsafe impl<T: ?Sized + Sync, const B: usize> Sync for Guard<'_, T, B> {}
+
 /// Deref trait 用于重载`不可变解引用`操作。
-impl<T> Deref for Guard<'_, T> {
+impl<T: ?Sized, const B: usize> Deref for Guard<'_, T, B> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         unsafe { &*self.lock.value.get() }
@@ -23,116 +322,3503 @@ impl<T> Deref for Guard<'_, T> {
 }
 
 /// DerefMut trait 用于重载`可变解引用`操作。
-impl<T> DerefMut for Guard<'_, T> {
+impl<T: ?Sized, const B: usize> DerefMut for Guard<'_, T, B> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         unsafe { &mut *self.lock.value.get() }
     }
 }
 
-impl<T> Drop for Guard<'_, T> {
+impl<T: ?Sized, const B: usize> Drop for Guard<'_, T, B> {
     fn drop(&mut self) {
-        self.lock
-            .locked
-            .store(false, std::sync::atomic::Ordering::Release);
+        unsafe { self.lock.drop_guard() };
+    }
+}
+
+/// `{:p}` prints the address of the lock the guard belongs to (not of the
+/// value), so logs from a program with many locks can tell them apart.
+impl<T: ?Sized, const B: usize> fmt::Pointer for Guard<'_, T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&self.lock, f)
+    }
+}
+
+/// A guard that only exposes part of the locked value, made by `Guard::map`.
+/// It keeps the original guard inside, so dropping it releases the lock
+/// exactly like the `Guard` would.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct MappedGuard<'a, T: ?Sized, U: ?Sized, const B: usize = 0> {
+    guard: Guard<'a, T, B>,
+    // Points into the value `guard` protects; we never touch `guard`'s view again.
+    value: *mut U,
+}
+
+unsafe impl<T: ?Sized + Sync, U: ?Sized + Sync, const B: usize> Sync for MappedGuard<'_, T, U, B> {}
+
+impl<'a, T: ?Sized, U: ?Sized, const B: usize> MappedGuard<'a, T, U, B> {
+    /// Narrows further, e.g. from a struct to one of its fields' fields.
+    /// Associated function for the same reason as `Guard::map`.
+    pub fn map<V: ?Sized>(
+        guard: Self,
+        f: impl FnOnce(&mut U) -> &mut V,
+    ) -> MappedGuard<'a, T, V, B> {
+        let value: *mut V = f(unsafe { &mut *guard.value });
+        MappedGuard {
+            guard: guard.guard,
+            value,
+        }
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, const B: usize> Deref for MappedGuard<'_, T, U, B> {
+    type Target = U;
+    fn deref(&self) -> &U {
+        unsafe { &*self.value }
+    }
+}
+
+impl<T: ?Sized, U: ?Sized, const B: usize> DerefMut for MappedGuard<'_, T, U, B> {
+    fn deref_mut(&mut self) -> &mut U {
+        unsafe { &mut *self.value }
+    }
+}
+
+/// An explicit handle to the locked value, made by `SpinLock::borrow_mut`.
+///
+/// Same as a `Guard` but without `Deref`/`DerefMut`: the value is only
+/// reached through `get` / `get_mut`, so method calls can't silently resolve
+/// to `T`'s methods instead of the handle's, or the other way round.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct RefMut<'a, T: ?Sized, const B: usize = 0> {
+    guard: Guard<'a, T, B>,
+}
+
+impl<T: ?Sized, const B: usize> RefMut<'_, T, B> {
+    pub fn get(&self) -> &T {
+        &self.guard
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+/// A borrowed guard, made by `Guard::token`: lets code that only has the
+/// `&SpinLock` read the value without locking it again.
+pub struct LockToken<'g, T: ?Sized, const B: usize = 0> {
+    // A reference to the guard (not the lock), so the token is exactly as
+    // `Send`/`Sync` as sharing the guard would be.
+    guard: &'g Guard<'g, T, B>,
+}
+
+/// A guard whose drop leaves the lock held, made by `Guard::disarm`.
+///
+/// Dropping one without `rearm`ing it leaks the lock, like `Guard::leak`:
+/// everyone else spins on it from then on, until `force_unlock`.
+#[must_use = "dropping a disarmed guard leaves the lock held"]
+pub struct DisarmedGuard<'a, T: ?Sized, const B: usize = 0> {
+    guard: ManuallyDrop<Guard<'a, T, B>>,
+}
+
+impl<'a, T: ?Sized, const B: usize> DisarmedGuard<'a, T, B> {
+    /// The normal guard back, releasing the lock when dropped. Associated
+    /// function for the same reason as `Guard::map`.
+    pub fn rearm(guard: Self) -> Guard<'a, T, B> {
+        ManuallyDrop::into_inner(guard.guard)
+    }
+}
+
+impl<T: ?Sized, const B: usize> Deref for DisarmedGuard<'_, T, B> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized, const B: usize> DerefMut for DisarmedGuard<'_, T, B> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+/// A guard that calls a closure once it has released the lock, made by
+/// `SpinLock::lock_with_on_unlock`.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct OnUnlockGuard<'a, T: ?Sized, F: FnOnce(), const B: usize = 0> {
+    // Dropped by hand, so the release happens before `on_unlock` runs.
+    guard: ManuallyDrop<Guard<'a, T, B>>,
+    on_unlock: ManuallyDrop<F>,
+}
+
+impl<T: ?Sized, F: FnOnce(), const B: usize> Deref for OnUnlockGuard<'_, T, F, B> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized, F: FnOnce(), const B: usize> DerefMut for OnUnlockGuard<'_, T, F, B> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T: ?Sized, F: FnOnce(), const B: usize> Drop for OnUnlockGuard<'_, T, F, B> {
+    fn drop(&mut self) {
+        // Both fields are dropped/taken exactly once, here.
+        let on_unlock = unsafe {
+            ManuallyDrop::drop(&mut self.guard);
+            ManuallyDrop::take(&mut self.on_unlock)
+        };
+        on_unlock();
     }
 }
 
-pub struct SpinLock<T> {
+/// A spin lock protecting a `T`.
+///
+/// `T` may be unsized, like `[T]` or `dyn Trait`: make a lock around a sized
+/// value and coerce a reference (or `Box`/`Arc`) to it, same as with `Mutex`.
+/// Everything but the methods that move `T` is available then.
+///
+/// ```
+/// use my_spin_lock::SpinLock;
+///
+/// let lock: &SpinLock<[i32]> = &SpinLock::new([1, 2, 3]);
+/// lock.lock_with_cas()[0] = 10;
+/// assert_eq!(lock.lock_with_cas().iter().sum::<i32>(), 15);
+/// ```
+///
+/// `SPIN_BUDGET` is how many failed attempts the `lock_with_swap`,
+/// `lock_with_cas` and `lock_with_ordering` loops make with `spin_loop()`
+/// before they start calling `yield_now()` after every further failure (with
+/// `std`; without it they keep spinning). Being part of the type it costs no
+/// field and no runtime check. The default of 0 means pure spinning, never
+/// yielding.
+///
+/// # Size
+///
+/// Without `std`, a release build adds two bytes to `T` (the lock and the
+/// poison flag), so a `SpinLock<u64>` is 16 bytes. `std` adds the runtime
+/// tuning of `lock_adaptive`: its spin count (a `usize`) and whether it is
+/// autotuned, making it 24 bytes. Debug builds and the `stats`, `tracing`,
+/// `park`, `smart` and `history` features each add their own bookkeeping on
+/// top.
+pub struct SpinLock<T: ?Sized, const SPIN_BUDGET: usize = 0> {
     // Using a boolean value to indicate
     // whether it is being locked.
     //
     // Using Atomic to make sure it can be
     // accessed simultaneously by multiple threads.
-    locked: AtomicBool,
+    // `RawSpinLock` wraps that atomic together with the basic lock/unlock.
+    raw: RawSpinLock,
+    // Set when a guard is dropped during a panic.
+    poisoned: AtomicBool,
+    #[cfg(feature = "stats")]
+    stats: stats::Counters,
+    // Debug builds remember the holder's `current_thread_id()` (0 if none),
+    // to catch a thread locking the same lock twice.
+    #[cfg(all(debug_assertions, feature = "std"))]
+    owner: core::sync::atomic::AtomicUsize,
+    // Debug builds count the live guards, which can only ever be 0 or 1
+    // unless unsafe code (`force_unlock` and friends) got it wrong.
+    #[cfg(debug_assertions)]
+    guards: core::sync::atomic::AtomicIsize,
+    // Debug builds: where the last guard was made, see `last_locked_at`.
+    #[cfg(debug_assertions)]
+    locked_at: core::sync::atomic::AtomicPtr<Location<'static>>,
+    // How long `lock_adaptive` spins before yielding, see `SpinLockBuilder`.
+    #[cfg(feature = "std")]
+    max_spins: usize,
+    // Use the process-wide measured spin count instead, see `new_autotuned`.
+    #[cfg(feature = "std")]
+    autotuned: bool,
+    // Moving average of `lock_smart` hold times, in nanoseconds.
+    #[cfg(feature = "smart")]
+    hold_nanos: core::sync::atomic::AtomicU64,
+    // The last few acquirers, see `acquisition_history`.
+    #[cfg(feature = "history")]
+    history: history::History,
+    // See `with_level`; release builds don't check, so have nothing to store.
+    #[cfg(all(debug_assertions, feature = "std"))]
+    level: Option<u32>,
+    // See `named`, only used by the `tracing` events.
+    #[cfg(feature = "tracing")]
+    name: Option<&'static str>,
+    // See `with_contention_threshold`.
+    #[cfg(all(feature = "tracing", feature = "std"))]
+    contention_threshold: Option<u64>,
+    // Threads sleeping in `lock_parking`, woken by every release.
+    #[cfg(feature = "park")]
+    waiters: park::WaitList,
     value: UnsafeCell<T>,
 }
 
 /// Impl Sync to ensures that our data can be shared between threads.
 /// `Sync` is more strict than `Send`, `Send` meaning can be transfered.
 /// But may not be shared simultaneously by many threads, unlike `RwLock`.
-unsafe impl<T> Sync for SpinLock<T> where T: Send {}
+///
+/// So a `!Sync` value like `Cell` becomes shareable behind the lock, but a
+/// `!Send` one like `Rc` doesn't: whoever locks could clone the `Rc` and
+/// race on its reference count with the thread that created it.
+///
+/// ```compile_fail
+/// use std::rc::Rc;
+/// use my_spin_lock::SpinLock;
+///
+/// let lock = SpinLock::new(Rc::new(0));
+/// std::thread::scope(|s| {
+///     s.spawn(|| drop(lock.lock_with_cas().clone()));
+/// });
+/// ```
+unsafe impl<T: ?Sized, const B: usize> Sync for SpinLock<T, B> where T: Send {}
+
+impl<T, const B: usize> SpinLock<T, B> {
+    const_fn! {
+        /// `new` for any `SPIN_BUDGET`, which comes from the type:
+        ///
+        /// ```
+        /// use my_spin_lock::SpinLock;
+        ///
+        /// let lock: SpinLock<i32, 16> = SpinLock::with_spin_budget(0);
+        /// *lock.lock_with_cas() += 1;
+        /// ```
+        pub const fn with_spin_budget(value: T) -> Self {
+            Self {
+                raw: RawSpinLock::new(),
+                poisoned: AtomicBool::new(false),
+                #[cfg(feature = "stats")]
+                stats: stats::Counters::new(),
+                #[cfg(all(debug_assertions, feature = "std"))]
+                owner: core::sync::atomic::AtomicUsize::new(0),
+                #[cfg(debug_assertions)]
+                guards: core::sync::atomic::AtomicIsize::new(0),
+                #[cfg(debug_assertions)]
+                locked_at: core::sync::atomic::AtomicPtr::new(core::ptr::null_mut()),
+                #[cfg(feature = "std")]
+                max_spins: Self::ADAPTIVE_SPINS,
+                #[cfg(feature = "std")]
+                autotuned: false,
+                #[cfg(feature = "smart")]
+                hold_nanos: core::sync::atomic::AtomicU64::new(0),
+                #[cfg(feature = "history")]
+                history: history::History::new(),
+                #[cfg(all(debug_assertions, feature = "std"))]
+                level: None,
+                #[cfg(feature = "tracing")]
+                name: None,
+                #[cfg(all(feature = "tracing", feature = "std"))]
+                contention_threshold: None,
+                #[cfg(feature = "park")]
+                waiters: park::WaitList::new(),
+                value: UnsafeCell::new(value),
+            }
+        }
+    }
+}
 
 impl<T> SpinLock<T> {
-    /// const fn are functions that can be called at compile-time
-    /// (by being used as the value of a const, or static),
-    pub const fn new(value: T) -> Self {
-        Self {
-            locked: AtomicBool::new(false),
-            value: UnsafeCell::new(value),
+    const_fn! {
+        /// const fn are functions that can be called at compile-time
+        /// (by being used as the value of a const, or static),
+        ///
+        /// The lock gets the default `SPIN_BUDGET` of 0; see `with_spin_budget`
+        /// for the others.
+        pub const fn new(value: T) -> Self {
+            Self::with_spin_budget(value)
+        }
+    }
+
+    /// `SpinLock::new(f())`, for call sites that read better as "initialize
+    /// with this", e.g. at the end of a builder chain. Calls `f` once, right
+    /// away; unlike `new` it can't be `const`, since closures can't be
+    /// called in a const context.
+    pub fn new_with(f: impl FnOnce() -> T) -> Self {
+        Self::new(f())
+    }
+
+    /// A lock whose `lock_adaptive` spins for a count measured on this
+    /// machine, instead of the fixed `ADAPTIVE_SPINS`. The first time any
+    /// such lock is contended, a quick measurement (well under a millisecond)
+    /// compares the cost of spinning with that of a `yield_now()` and picks
+    /// the longest spin that is still cheaper; the result is shared by all
+    /// autotuned locks for the rest of the process. Uncontended locking
+    /// never measures anything.
+    #[cfg(feature = "std")]
+    pub fn new_autotuned(value: T) -> Self {
+        let mut lock = Self::new(value);
+        lock.autotuned = true;
+        lock
+    }
+
+    const_fn! {
+        /// Puts an existing `UnsafeCell` behind the lock, unlocked. For code
+        /// that already owns a cell and wants to add locking around it.
+        pub const fn from_cell(cell: UnsafeCell<T>) -> Self {
+            Self::new(cell.into_inner())
+        }
+    }
+
+    const_fn! {
+        /// A lock that starts out held, e.g. a `static` that must not be used
+        /// before some initialization is done. No guard exists for this first
+        /// hold: whoever does the initialization releases it with `unlock`
+        /// (or `force_unlock`), after which the lock behaves normally.
+        ///
+        /// Until then every `lock_*` spins and every `try_lock` fails; the
+        /// creator has to make sure the release actually happens, and must
+        /// only touch the value (through `as_mut_ptr`) before it does, while
+        /// nobody else can.
+        pub const fn new_locked(value: T) -> Self {
+            let mut lock = Self::new(value);
+            lock.raw.locked = AtomicBool::new(true);
+            lock
+        }
+    }
+
+    const_fn! {
+        /// A lock that takes part in a lock hierarchy: in debug builds (with
+        /// `std`) a thread holding levelled locks may only lock levels strictly
+        /// *lower* than all of them, and panics otherwise. If every levelled
+        /// lock is only ever taken top-down, no two threads can wait on each
+        /// other in a cycle. Locks made with `new` are not checked.
+        ///
+        /// ```
+        /// use my_spin_lock::SpinLock;
+        ///
+        /// let accounts = SpinLock::with_level(vec![1, 2], 2);
+        /// let log = SpinLock::with_level(Vec::<String>::new(), 1);
+        /// let accounts = accounts.lock_with_cas();
+        /// // Fine, 1 < 2. Locking `accounts` while holding `log` would panic.
+        /// log.lock_with_cas().push(format!("{:?}", *accounts));
+        /// ```
+        pub const fn with_level(value: T, level: u32) -> Self {
+            #[cfg(all(debug_assertions, feature = "std"))]
+            {
+                let mut lock = Self::new(value);
+                lock.level = Some(level);
+                lock
+            }
+            #[cfg(not(all(debug_assertions, feature = "std")))]
+            {
+                let _ = level;
+                Self::new(value)
+            }
+        }
+    }
+
+    const_fn! {
+        /// A lock with a name, which the `tracing` feature's acquire/release
+        /// events carry so hot locks can be told apart. Without the feature the
+        /// name is dropped, and the lock has no field to keep it in.
+        pub const fn named(value: T, name: &'static str) -> Self {
+            #[cfg(feature = "tracing")]
+            {
+                let mut lock = Self::new(value);
+                lock.name = Some(name);
+                lock
+            }
+            #[cfg(not(feature = "tracing"))]
+            {
+                let _ = name;
+                Self::new(value)
+            }
+        }
+    }
+
+    const_fn! {
+        /// `named`, plus a `warn!` whenever an acquisition had to spin more
+        /// than `threshold` times before getting the lock, with the lock's
+        /// name and the spin count. Surfaces the hot spots in production logs
+        /// without an event per acquisition.
+        ///
+        /// The spins are counted per thread, so waiting on other locks doesn't
+        /// add up; locks without a threshold skip the counting altogether.
+        #[cfg(all(feature = "tracing", feature = "std"))]
+        pub const fn with_contention_threshold(value: T, name: &'static str, threshold: u64) -> Self {
+            let mut lock = Self::named(value, name);
+            lock.contention_threshold = Some(threshold);
+            lock
+        }
+    }
+
+    const_fn! {
+        /// Wraps every element in its own lock, usable in a `static`:
+        ///
+        /// ```
+        /// use my_spin_lock::SpinLock;
+        ///
+        /// static SHARDS: [SpinLock<Vec<u8>>; 3] =
+        ///     SpinLock::from_array([Vec::new(), Vec::new(), Vec::new()]);
+        /// SHARDS[1].lock_with_cas().push(7);
+        /// ```
+        ///
+        /// Unlike `[const { SpinLock::new(..) }; N]` the elements can differ.
+        pub const fn from_array<const N: usize>(values: [T; N]) -> [SpinLock<T>; N] {
+            // Every element gets moved out exactly once below.
+            let values = core::mem::ManuallyDrop::new(values);
+            let src = &values as *const core::mem::ManuallyDrop<[T; N]> as *const T;
+            let mut locks = [const { core::mem::MaybeUninit::<SpinLock<T>>::uninit() }; N];
+            let mut i = 0;
+            while i < N {
+                locks[i] = core::mem::MaybeUninit::new(Self::new(unsafe { src.add(i).read() }));
+                i += 1;
+            }
+            // All `N` are initialized, and `MaybeUninit<X>` has the layout of `X`.
+            unsafe { (&locks as *const _ as *const [SpinLock<T>; N]).read() }
         }
     }
+}
+
+impl<T: ?Sized, const B: usize> SpinLock<T, B> {
+    /// The everyday way to lock, named like `Mutex::lock` so code can switch
+    /// over. By default (feature `default-cas`) this is `lock_with_backoff`,
+    /// a CAS loop that backs off when contended; with `default-swap` instead
+    /// it is `lock_with_swap`. Which strategy the default picks may change
+    /// between releases, call one of the `lock_with_*` if it matters to you.
+    ///
+    /// Unlike `Mutex::lock` there is no `LockResult` to unwrap, since a spin
+    /// lock is mostly used without poisoning; `lock_checked` reports it.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    #[cfg_attr(feature = "profiling", inline(never))]
+    #[cfg_attr(not(feature = "profiling"), inline)]
+    pub fn lock(&self) -> Guard<'_, T, B> {
+        #[cfg(feature = "default-swap")]
+        return self.lock_with_swap();
+        #[cfg(not(feature = "default-swap"))]
+        self.lock_with_backoff()
+    }
 
     /// locked starts as false，lock() try to change it to true and keep trying.
-    pub fn lock_with_swap(&self) -> Guard<T> {
-        while self.locked.swap(true, std::sync::atomic::Ordering::Acquire) {
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_with_swap(&self) -> Guard<'_, T, B> {
+        self.check_deadlock();
+        let mut attempts = 0;
+        while self
+            .raw
+            .locked
+            .swap(true, core::sync::atomic::Ordering::Acquire)
+        {
             // use hint::spin_lock to tell cpu that we're self-spinning
             // and is wating for a change.
             // spin_loop() differs largely from `thread::sleep()` and `thread::park()`
             // it will NOT leads to syscall that makes our thread fall asleep.
-            std::hint::spin_loop();
+            spin_loop();
+            self.spin_wait(&mut attempts);
         }
-        Guard { lock: self }
+        Guard::new(self)
     }
 
     /// Besides use `swap`, we can also use `CAS`(compare and exchange) ops
     /// to automatically check whether the boolean value is false.
     /// If it is, then we set it to true. This method is more understanable.
-    pub fn lock_with_cas(&self) -> Guard<T> {
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_with_cas(&self) -> Guard<'_, T, B> {
+        self.check_deadlock();
+        let mut attempts = 0;
+        self.raw.lock_and(|| self.spin_wait(&mut attempts));
+        Guard::new(self)
+    }
+
+    /// Test-and-test-and-set: like `lock_with_cas`, but after a failed
+    /// attempt it waits with plain `Relaxed` loads until the lock looks free,
+    /// and only then tries the CAS again.
+    ///
+    /// Every CAS needs the cache line exclusive, even one that fails, so with
+    /// `lock_with_cas` the waiters keep stealing the line from each other and
+    /// from the holder. Loads can share the line, it stays put in every
+    /// waiter's cache until the release store invalidates it. Mostly pays off
+    /// with many cores contending; the `acquisition` benchmark compares them.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_ttas(&self) -> Guard<'_, T, B> {
+        self.check_deadlock();
+        let mut attempts = 0;
+        while self
+            .raw
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            while self.raw.locked.load(Ordering::Relaxed) {
+                spin_loop();
+                self.spin_wait(&mut attempts);
+            }
+        }
+        Guard::new(self)
+    }
+
+    /// `lock_with_cas` that also says how many times it had to spin, i.e.
+    /// how contended this particular acquisition was; 0 means it got the lock
+    /// at the first try. Needs no `stats` feature.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_counting(&self) -> (Guard<'_, T, B>, u64) {
+        self.check_deadlock();
+        let mut attempts = 0;
+        let mut spins = 0;
+        self.raw.lock_and(|| {
+            spins += 1;
+            self.spin_wait(&mut attempts);
+        });
+        (Guard::new(self), spins)
+    }
+
+    /// `lock_counting` boiled down to "did it have to wait at all", e.g. for
+    /// sampling how often a lock is contended: `false` if the first attempt
+    /// got the lock, `true` if it had to spin.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_reporting(&self) -> (Guard<'_, T, B>, bool) {
+        self.check_deadlock();
+        let mut attempts = 0;
+        let mut waited = false;
+        self.raw.lock_and(|| {
+            waited = true;
+            self.spin_wait(&mut attempts);
+        });
+        (Guard::new(self), waited)
+    }
+
+    /// CAS loop that waits a random number of `spin_loop()`s after each
+    /// failed attempt, so threads that all saw the lock freed at once don't
+    /// keep colliding on the next CAS too. The range starts at `0..4` and
+    /// doubles per failure up to `0..256`, which bounds the wait.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_with_jitter(&self) -> Guard<'_, T, B> {
+        const MAX_WINDOW: u32 = 256;
+        self.check_deadlock();
+        let mut rng = jitter::XorShift::for_this_thread();
+        let mut window = 4;
+        self.raw.lock_and(|| {
+            self.record_spin();
+            for _ in 0..rng.below(window) {
+                spin_loop();
+            }
+            window = (window * 2).min(MAX_WINDOW);
+        });
+        Guard::new(self)
+    }
+
+    /// CAS loop with caller-chosen orderings, for experimenting on weakly-ordered
+    /// hardware such as ARM. `lock_with_cas` is this with `Acquire`/`Relaxed`.
+    ///
+    /// For the lock to actually protect anything, `success` must be at least
+    /// `Acquire` (`Acquire`, `AcqRel` or `SeqCst`), pairing with the guard's
+    /// `Release` store on unlock. That is checked with a `debug_assert!`.
+    /// `failure` only applies to attempts that didn't take the lock, so it may
+    /// be anything `compare_exchange` accepts for a failure ordering.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_with_ordering(&self, success: Ordering, failure: Ordering) -> Guard<'_, T, B> {
+        self.check_deadlock();
+        debug_assert!(
+            matches!(
+                success,
+                Ordering::Acquire | Ordering::AcqRel | Ordering::SeqCst
+            ),
+            "lock_with_ordering: success ordering {success:?} is weaker than Acquire"
+        );
+        let mut attempts = 0;
+        while self
+            .raw
+            .locked
+            .compare_exchange_weak(false, true, success, failure)
+            .is_err()
+        {
+            spin_loop();
+            self.spin_wait(&mut attempts);
+        }
+        Guard::new(self)
+    }
+
+    /// Same CAS loop as `lock_with_cas`, but backs off exponentially when contended.
+    /// Every failed attempt doubles how many times we call `spin_loop()` before
+    /// trying again, so waiting threads stop hammering the cache line.
+    /// Once the spin count reaches 64 we `yield_now()` instead,
+    /// giving the holder a chance to run and release the lock.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_with_backoff(&self) -> Guard<'_, T, B> {
+        self.lock_with(&mut Backoff::new())
+    }
+
+    /// CAS loop that waits with the given `Backoff` between failed attempts.
+    /// The backoff is reset first, so one instance can be reused for many
+    /// acquisitions; an uncontended acquisition never snoozes at all.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_with(&self, backoff: &mut Backoff) -> Guard<'_, T, B> {
+        self.check_deadlock();
+        backoff.reset();
         while self
+            .raw
             .locked
             .compare_exchange_weak(
                 false,
                 true,
-                std::sync::atomic::Ordering::Acquire,
-                std::sync::atomic::Ordering::Relaxed,
+                core::sync::atomic::Ordering::Acquire,
+                core::sync::atomic::Ordering::Relaxed,
             )
             .is_err()
-        {}
-        Guard { lock: self }
+        {
+            backoff.snooze();
+            self.record_spin();
+        }
+        Guard::new(self)
     }
 
-    /// Safety: The &mut T from lock() must be gone!
-    /// (And no cheating by keeping reference to fields of that T around!)
-    /// unlock method only trans it to false.
-    pub unsafe fn unlock(&self) {
-        self.locked
-            .store(false, std::sync::atomic::Ordering::Release);
+    /// CAS loop that calls `relax.relax()` after every failed attempt, for
+    /// plugging in a wait strategy of your own. `lock_with_cas` is this with
+    /// `SpinRelax`, `lock_yielding` with `YieldRelax`.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_relax<R: Relax + ?Sized>(&self, relax: &mut R) -> Guard<'_, T, B> {
+        self.check_deadlock();
+        while self
+            .raw
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            relax.relax();
+            self.record_spin();
+        }
+        Guard::new(self)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::thread;
+    /// Calls `yield_now()` after every failed attempt instead of spinning.
+    ///
+    /// For short critical sections where the contention comes from threads
+    /// sharing the same cores, e.g. an async runtime's worker pool: the waiter
+    /// gives its time slice to whoever may be holding the lock (or to other
+    /// tasks) right away instead of burning it.
+    #[cfg(feature = "std")]
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_yielding(&self) -> Guard<'_, T, B> {
+        self.check_deadlock();
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            std::thread::yield_now();
+            self.record_spin();
+        }
+    }
 
-    use super::*;
+    /// How many times `lock_adaptive` spins before it starts yielding, unless
+    /// the lock was built with `SpinLockBuilder::max_spins`.
+    pub const ADAPTIVE_SPINS: usize = 128;
+    /// How many times `lock_adaptive` yields before it starts sleeping.
+    pub const ADAPTIVE_YIELDS: usize = 16;
 
-    #[test]
-    fn it_works_with_swap() {
-        let x = SpinLock::new(Vec::new());
-        thread::scope(|s| {
-            s.spawn(|| x.lock_with_swap().push(42));
-            s.spawn(|| {
-                let mut g = x.lock_with_swap();
-                g.push(21);
-                g.push(21);
-            });
-        });
-        let g = x.lock_with_swap();
-        assert!(g.as_slice() == [42, 21, 21] || g.as_slice() == [21, 21, 42]);
+    /// For locks whose holder may get descheduled, e.g. with more threads than
+    /// cores. Pure spinning would then burn the waiter's whole time slice, so
+    /// this escalates in three stages:
+    /// 1. spin with `spin_loop()` for `ADAPTIVE_SPINS` attempts (or the
+    ///    builder's `max_spins`, or the measured count of `new_autotuned`),
+    /// 2. `yield_now()` for `ADAPTIVE_YIELDS` attempts,
+    /// 3. `sleep()`, starting at 1µs and doubling up to 1ms.
+    #[cfg(feature = "std")]
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_adaptive(&self) -> Guard<'_, T, B> {
+        self.check_deadlock();
+        const MAX_SLEEP: Duration = Duration::from_millis(1);
+        let mut attempts = 0;
+        let mut sleep = Duration::from_micros(1);
+        let mut max_spins = None;
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            // Only now, so an uncontended lock never pays for the tuning.
+            let max_spins = *max_spins.get_or_insert_with(|| self.spins_before_yield());
+            if attempts < max_spins {
+                spin_loop();
+            } else if attempts < max_spins.saturating_add(Self::ADAPTIVE_YIELDS) {
+                std::thread::yield_now();
+            } else {
+                std::thread::sleep(sleep);
+                sleep = (sleep * 2).min(MAX_SLEEP);
+            }
+            attempts = attempts.saturating_add(1);
+            self.record_spin();
+        }
     }
 
-    #[test]
-    fn it_works_with_cas() {
-        let x = SpinLock::new(Vec::new());
-        thread::scope(|s| {
-            s.spawn(|| x.lock_with_cas().push(1));
-            s.spawn(|| {
-                let mut g = x.lock_with_cas();
-                g.push(2);
-                g.push(2);
-            });
-        });
-        let g = x.lock_with_cas();
-        assert!(g.as_slice() == [1, 2, 2] || g.as_slice() == [2, 2, 1]);
+    /// Like `lock_with_swap`, but reports whether a previous holder panicked.
+    /// The lock is held either way; see `PoisonError::into_inner`.
+    #[track_caller]
+    pub fn lock_with_swap_checked(&self) -> LockResult<Guard<'_, T, B>> {
+        self.check_poison(self.lock_with_swap())
+    }
+
+    /// Like `lock_with_cas`, but reports whether a previous holder panicked.
+    #[track_caller]
+    pub fn lock_with_cas_checked(&self) -> LockResult<Guard<'_, T, B>> {
+        self.check_poison(self.lock_with_cas())
+    }
+
+    /// One lock method that reports every failure instead of panicking:
+    /// poisoning (with the guard, as in `lock_with_cas_checked`) and, in
+    /// debug builds with `std`, locking that could only deadlock, where
+    /// `lock_with_cas` would panic.
+    #[track_caller]
+    pub fn lock_checked(&self) -> Result<Guard<'_, T, B>, LockError<Guard<'_, T, B>>> {
+        if self.would_deadlock() {
+            return Err(LockError::WouldDeadlock);
+        }
+        Ok(self.lock_with_cas_checked()?)
+    }
+
+    fn check_poison<'a>(&self, guard: Guard<'a, T, B>) -> LockResult<Guard<'a, T, B>> {
+        if self.is_poisoned() {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    /// `true` if a thread panicked while holding the lock.
+    /// The plain lock methods ignore this, only the `_checked` ones report it.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(core::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Marks the data as consistent again after a caller has dealt with the
+    /// fallout of a panic.
+    pub fn clear_poison(&self) {
+        self.poisoned
+            .store(false, core::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Locks like `lock_with_cas`, and if a previous holder panicked lets
+    /// `recover` repair the value before clearing the poison flag. The
+    /// guard is then as good as one from an unpoisoned lock. Since the lock
+    /// is held throughout, `recover` runs at most once per panic.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_or_recover(&self, recover: impl FnOnce(&mut T)) -> Guard<'_, T, B> {
+        let mut guard = self.lock_with_cas();
+        if self.is_poisoned() {
+            recover(&mut guard);
+            self.clear_poison();
+        }
+        guard
+    }
+
+    /// Runs `f` with the lock held and returns whatever it returns.
+    /// The guard lives exactly as long as the closure, and since it is dropped
+    /// during unwinding too, the lock is released even if `f` panics.
+    pub fn with_lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let mut guard = self.lock_with_cas();
+        f(&mut *guard)
+    }
+
+    /// `with_lock` that contains a panic in `f`: it is caught, the lock is
+    /// released and the panic payload comes back in `Err` instead of
+    /// unwinding into the caller, e.g. to keep a misbehaving plugin callback
+    /// from taking the host down. `f` may have left the value half-updated,
+    /// so the lock is poisoned then, same as if the panic had escaped.
+    #[cfg(feature = "std")]
+    pub fn with_lock_catch<R>(
+        &self,
+        f: impl FnOnce(&mut T) -> R + std::panic::UnwindSafe,
+    ) -> std::thread::Result<R> {
+        let mut guard = self.lock_with_cas();
+        // The `&mut T` is what isn't unwind safe, poisoning covers it.
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(&mut guard)));
+        if result.is_err() {
+            self.poisoned
+                .store(true, core::sync::atomic::Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// `with_lock` that never waits: runs `f` only if `try_lock` succeeds,
+    /// otherwise returns `None` right away. For optional bookkeeping the
+    /// caller can skip. The lock is released even if `f` panics.
+    pub fn try_with<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        let mut guard = self.try_lock()?;
+        Some(f(&mut guard))
+    }
+
+    /// `with_lock` with a bound on the wait: runs `f` if the lock can be taken
+    /// within `timeout` (via `try_lock_for`), otherwise gives up with `None`.
+    #[cfg(feature = "std")]
+    pub fn with_lock_timeout<R>(
+        &self,
+        timeout: Duration,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> Option<R> {
+        let mut guard = self.try_lock_for(timeout)?;
+        Some(f(&mut guard))
+    }
+
+    /// Read access to the value during a critical section the caller is
+    /// already in, proven by a token from its guard (see `Guard::token`).
+    /// Only `&T`: the guard itself may still be read, but two `&mut T` at
+    /// once would be unsound.
+    ///
+    /// # Panics
+    ///
+    /// If `token` comes from a guard of another lock.
+    pub fn reenter<'g>(&self, token: &LockToken<'g, T, B>) -> &'g T {
+        assert!(
+            core::ptr::eq(token.guard.lock, self),
+            "LockToken belongs to a different SpinLock"
+        );
+        Guard::get(token.guard)
+    }
+
+    /// `lock_with_cas`, returning a `RefMut` instead of a `Guard` for
+    /// those who'd rather spell out `.get()` / `.get_mut()` than deref.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn borrow_mut(&self) -> RefMut<'_, T, B> {
+        RefMut {
+            guard: self.lock_with_cas(),
+        }
+    }
+
+    /// Locks, and once the returned guard has released the lock again, calls
+    /// `on_unlock`. For work that should happen right after the critical
+    /// section but outside of it, like notifying a condvar or a waiting
+    /// thread: by the time `on_unlock` runs, the release store is done and
+    /// whoever it wakes can take the lock straight away.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_with_on_unlock<F: FnOnce()>(&self, on_unlock: F) -> OnUnlockGuard<'_, T, F, B> {
+        OnUnlockGuard {
+            guard: ManuallyDrop::new(self.lock_with_cas()),
+            on_unlock: ManuallyDrop::new(on_unlock),
+        }
+    }
+
+    /// `with_lock` for plain mutation, reads a bit better when there is
+    /// nothing to return. Also releases the lock if `f` panics.
+    pub fn update(&self, f: impl FnOnce(&mut T)) {
+        self.with_lock(f)
+    }
+
+    /// Applies `f` only if `pred` holds for the current value, both under the
+    /// same lock so nobody can change the value in between. Returns whether
+    /// `f` ran.
+    pub fn update_if(&self, pred: impl FnOnce(&T) -> bool, f: impl FnOnce(&mut T)) -> bool {
+        let mut guard = self.lock_with_cas();
+        let matched = pred(&guard);
+        if matched {
+            f(&mut guard);
+        }
+        matched
+    }
+
+    /// Makes a single attempt to take the lock and never spins.
+    /// Returns `None` straight away if someone else is holding it,
+    /// so the caller can go and do other work instead of waiting.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    #[cfg_attr(feature = "profiling", inline(never))]
+    #[cfg_attr(not(feature = "profiling"), inline)]
+    pub fn try_lock(&self) -> Option<Guard<'_, T, B>> {
+        // Not `then(|| ..)`: a closure would hide our caller from `Guard::new`.
+        if self.raw.try_lock() {
+            Some(Guard::new(self))
+        } else {
+            None
+        }
+    }
+
+    /// `try_lock`, keeping the guard only if `pred` accepts the value, e.g.
+    /// "take this connection if it is free *and* still open". `pred` runs with
+    /// the lock held, so the value can't change between the check and the
+    /// caller using it. If the lock is taken or `pred` says no, the lock is
+    /// left as it was and this returns `None`.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_if_unlocked_and<F: FnOnce(&T) -> bool>(&self, pred: F) -> Option<Guard<'_, T, B>> {
+        let guard = self.try_lock()?;
+        pred(&guard).then_some(guard)
+    }
+
+    /// A single `compare_exchange_weak`, for callers that retry in their own
+    /// loop. Unlike `try_lock` it may fail spuriously even when the lock is
+    /// free, so `Err(())` only means "didn't get it this time"; on some
+    /// architectures (LL/SC ones like ARM) that makes each attempt cheaper.
+    #[allow(clippy::result_unit_err)]
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn try_lock_weak(&self) -> Result<Guard<'_, T, B>, ()> {
+        match self.raw.locked.compare_exchange_weak(
+            false,
+            true,
+            core::sync::atomic::Ordering::Acquire,
+            core::sync::atomic::Ordering::Relaxed,
+        ) {
+            Ok(_) => Ok(Guard::new(self)),
+            Err(_) => Err(()),
+        }
+    }
+
+    /// Tries to take the lock, then retries up to `max_spins` more times with
+    /// `spin_loop()` in between. A clock-free way to bound the wait, e.g. in
+    /// `no_std` or in tests; `try_lock_spins(0)` is just `try_lock()`.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn try_lock_spins(&self, max_spins: usize) -> Option<Guard<'_, T, B>> {
+        for _ in 0..max_spins {
+            if let Some(guard) = self.try_lock() {
+                return Some(guard);
+            }
+            spin_loop();
+            self.record_spin();
+        }
+        let guard = self.try_lock();
+        if guard.is_none() {
+            self.gave_up();
+        }
+        guard
+    }
+
+    /// Like `try_lock_spins`, but waits longer after each failure, as
+    /// `lock_with_backoff` does: gives up with `None` after `max_attempts`
+    /// failed tries (there's always at least one). Some persistence for
+    /// latency-sensitive code, without waiting for the lock indefinitely.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn try_lock_backoff(&self, max_attempts: u32) -> Option<Guard<'_, T, B>> {
+        let mut backoff = Backoff::new();
+        let mut failures = 0;
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return Some(guard);
+            }
+            failures += 1;
+            if failures >= max_attempts {
+                self.gave_up();
+                return None;
+            }
+            backoff.snooze();
+            self.record_spin();
+        }
+    }
+
+    /// `try_lock` as a `Poll`, for taking the lock from a hand-written
+    /// `Future::poll`. It registers no waker: the lock has no way to wake a
+    /// task on release, so after `Pending` the caller has to arrange to be
+    /// polled again itself (e.g. `cx.waker().wake_by_ref()` to retry soon).
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn poll_lock(&self) -> core::task::Poll<Guard<'_, T, B>> {
+        match self.try_lock() {
+            Some(guard) => core::task::Poll::Ready(guard),
+            None => core::task::Poll::Pending,
+        }
+    }
+
+    /// `try_lock` for callers that retry in a loop of their own: if the lock
+    /// is held, yields the CPU before returning `None`, so a holder sharing
+    /// the core gets to run and finish. That costs a trip through the
+    /// scheduler on every failure, so use it where fairness matters more than
+    /// how quickly `None` comes back.
+    #[cfg(feature = "std")]
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn try_lock_fair(&self) -> Option<Guard<'_, T, B>> {
+        let guard = self.try_lock();
+        if guard.is_none() {
+            std::thread::yield_now();
+        }
+        guard
+    }
+
+    /// Keeps trying to take the lock until `timeout` has passed, then gives up
+    /// and returns `None`. See `try_lock_until`.
+    #[cfg(feature = "std")]
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn try_lock_for(&self, timeout: Duration) -> Option<Guard<'_, T, B>> {
+        match Instant::now().checked_add(timeout) {
+            Some(deadline) => self.try_lock_until(deadline),
+            // Too far in the future to even represent: just wait for it.
+            None => Some(self.lock_with_cas()),
+        }
+    }
+
+    /// Keeps trying to take the lock until `deadline`, then gives up and returns
+    /// `None`. A deadline that has already passed still gets one attempt,
+    /// so this never blocks when the deadline is in the past.
+    /// Reading the clock is much slower than a CAS, so we only look at it
+    /// every `CLOCK_CHECK_INTERVAL` spins.
+    #[cfg(feature = "std")]
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn try_lock_until(&self, deadline: Instant) -> Option<Guard<'_, T, B>> {
+        const CLOCK_CHECK_INTERVAL: u32 = 64;
+        let mut spins = 0;
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return Some(guard);
+            }
+            if spins == 0 && Instant::now() >= deadline {
+                self.gave_up();
+                return None;
+            }
+            spins = (spins + 1) % CLOCK_CHECK_INTERVAL;
+            spin_loop();
+            self.record_spin();
+        }
+    }
+
+    /// Spins like `lock_with_cas` until it gets the lock, or until `cancel`
+    /// is set, e.g. by a supervisor thread giving up on a stuck holder; then
+    /// it returns `None`. The flag is checked (with a `Relaxed` load) after
+    /// every failed attempt, so a free lock is still taken even if `cancel`
+    /// is already set.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_cancellable(
+        &self,
+        cancel: &core::sync::atomic::AtomicBool,
+    ) -> Option<Guard<'_, T, B>> {
+        self.check_deadlock();
+        let mut attempts = 0;
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return Some(guard);
+            }
+            if cancel.load(Ordering::Relaxed) {
+                self.gave_up();
+                return None;
+            }
+            spin_loop();
+            self.spin_wait(&mut attempts);
+        }
+    }
+
+    /// Contention counters collected since the lock was created.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> LockStats {
+        self.stats.snapshot()
+    }
+
+    /// The `stats()` counters as Prometheus text-format samples, labelled
+    /// with `name`:
+    ///
+    /// ```text
+    /// spinlock_acquisitions_total{name="queue"} 42
+    /// spinlock_spins_total{name="queue"} 7
+    /// ```
+    ///
+    /// There are no `# TYPE` lines, so the output of several locks can simply
+    /// be concatenated into one scrape response.
+    #[cfg(all(feature = "stats", feature = "std"))]
+    pub fn export_metrics(&self, name: &str) -> String {
+        self.stats().to_prometheus(name)
+    }
+
+    /// What every new guard does, right after the lock was taken.
+    #[track_caller]
+    fn acquired(&self) {
+        #[cfg(feature = "stats")]
+        self.stats.acquired();
+        #[cfg(feature = "history")]
+        self.history.record(Location::caller());
+        #[cfg(feature = "tracing")]
+        tracing::trace!(lock = self.name.unwrap_or("<unnamed>"), "spinlock acquired");
+        #[cfg(all(feature = "tracing", feature = "std"))]
+        if let Some(threshold) = self.contention_threshold {
+            let spins = CONTENDED_SPINS.with(|spins| spins.replace(0));
+            if spins > threshold {
+                tracing::warn!(
+                    lock = self.name.unwrap_or("<unnamed>"),
+                    spins,
+                    threshold,
+                    "contended spinlock acquisition"
+                );
+            }
+        }
+        #[cfg(debug_assertions)]
+        {
+            let at: *const Location<'static> = Location::caller();
+            self.locked_at
+                .store(at.cast_mut(), core::sync::atomic::Ordering::Relaxed);
+            // The lock orders these, `Relaxed` is enough.
+            let live = self
+                .guards
+                .fetch_add(1, core::sync::atomic::Ordering::Relaxed)
+                + 1;
+            debug_assert!(live <= 1, "{live} guards alive for one SpinLock");
+        }
+        #[cfg(all(debug_assertions, feature = "std"))]
+        {
+            self.owner
+                .store(current_thread_id(), core::sync::atomic::Ordering::Relaxed);
+            if let Some(level) = self.level {
+                hierarchy::push(level, self as *const Self as *const () as usize);
+            }
+        }
+    }
+
+    /// Debug builds only: panics instead of spinning forever when the calling
+    /// thread already holds this lock, or when locking would break the
+    /// `with_level` hierarchy. Compiles to nothing in release builds.
+    #[inline(always)]
+    fn check_deadlock(&self) {
+        #[cfg(all(debug_assertions, feature = "std"))]
+        {
+            if self.owner.load(core::sync::atomic::Ordering::Relaxed) == current_thread_id() {
+                match self.last_locked_at() {
+                    Some(at) => {
+                        panic!("re-entrant lock on non-reentrant SpinLock, held since {at}")
+                    }
+                    None => panic!("re-entrant lock on non-reentrant SpinLock"),
+                }
+            }
+            if let Some(level) = self.level {
+                hierarchy::check(level);
+            }
+        }
+    }
+
+    /// What `check_deadlock` would panic on, as a `bool`.
+    fn would_deadlock(&self) -> bool {
+        #[cfg(all(debug_assertions, feature = "std"))]
+        {
+            if self.owner.load(core::sync::atomic::Ordering::Relaxed) == current_thread_id() {
+                return true;
+            }
+            if let Some(level) = self.level {
+                return !hierarchy::allows(level);
+            }
+        }
+        false
+    }
+
+    /// For the unlocks that bypass the guard: whatever guard there was has
+    /// been forgotten, so it doesn't count any more.
+    fn guards_forgotten(&self) {
+        #[cfg(debug_assertions)]
+        self.guards.store(0, core::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Forgets which thread holds the lock, for guards that may be sent to
+    /// another thread; the debug checks can't follow those around.
+    fn disown(&self) {
+        #[cfg(all(debug_assertions, feature = "std"))]
+        {
+            self.owner.store(0, core::sync::atomic::Ordering::Relaxed);
+            if let Some(level) = self.level {
+                hierarchy::pop(level, self as *const Self as *const () as usize);
+            }
+        }
+    }
+
+    /// What every guard does when it goes away.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called by the guard that holds the lock, once.
+    unsafe fn drop_guard(&self) {
+        // Dropped while unwinding: the closure/critical section didn't finish,
+        // so warn the next holder. The release store below publishes this.
+        #[cfg(feature = "std")]
+        if std::thread::panicking() {
+            self.poisoned
+                .store(true, core::sync::atomic::Ordering::Relaxed);
+        }
+        self.disown();
+        #[cfg(debug_assertions)]
+        self.guards
+            .fetch_sub(1, core::sync::atomic::Ordering::Relaxed);
+        self.release();
+        #[cfg(feature = "tracing")]
+        tracing::trace!(lock = self.name.unwrap_or("<unnamed>"), "spinlock released");
+    }
+
+    /// The one place a `SpinLock` is released, for guards and `unlock` /
+    /// `force_unlock` alike.
+    ///
+    /// # Safety
+    ///
+    /// Same as `RawSpinLock::unlock`.
+    unsafe fn release(&self) {
+        self.release_with(raw::UNLOCK);
+    }
+
+    /// `release` with the store's ordering picked by the caller (only
+    /// `unlock_with` picks anything but `raw::UNLOCK`), plus waking a thread
+    /// parked in `lock_parking`.
+    ///
+    /// # Safety
+    ///
+    /// Same as `RawSpinLock::unlock`.
+    unsafe fn release_with(&self, ordering: Ordering) {
+        debug_assert!(
+            matches!(ordering, Ordering::Release | Ordering::SeqCst),
+            "unlocking must be a Release store, or the next holder may not see our writes"
+        );
+        self.raw.locked.store(false, ordering);
+        #[cfg(feature = "park")]
+        self.waiters.wake_one();
+    }
+
+    /// Stage 1 of `lock_adaptive`.
+    #[cfg(feature = "std")]
+    fn spins_before_yield(&self) -> usize {
+        if self.autotuned {
+            autotune::spins()
+        } else {
+            self.max_spins
+        }
+    }
+
+    /// The `SPIN_BUDGET` part of a spin loop, once per failed attempt (the
+    /// `spin_loop()` itself is up to the caller). Folds away for a budget of 0.
+    #[inline(always)]
+    fn spin_wait(&self, attempts: &mut usize) {
+        self.record_spin();
+        if B == 0 {
+            return;
+        }
+        if *attempts < B {
+            *attempts += 1;
+        } else {
+            #[cfg(feature = "std")]
+            std::thread::yield_now();
+        }
+    }
+
+    /// Called once per trip round a spin loop; free without the `stats` feature.
+    #[inline(always)]
+    fn record_spin(&self) {
+        #[cfg(feature = "stats")]
+        self.stats.spun();
+        #[cfg(all(feature = "tracing", feature = "std"))]
+        if self.contention_threshold.is_some() {
+            CONTENDED_SPINS.with(|spins| spins.set(spins.get() + 1));
+        }
+    }
+
+    /// For every way out of a spin loop that doesn't end with the lock taken:
+    /// those spins mustn't count towards the next acquisition's
+    /// `with_contention_threshold`.
+    #[inline(always)]
+    fn gave_up(&self) {
+        #[cfg(all(feature = "tracing", feature = "std"))]
+        if self.contention_threshold.is_some() {
+            CONTENDED_SPINS.with(|spins| spins.set(0));
+        }
+    }
+
+    /// Reports whether the lock is currently held, without trying to take it.
+    ///
+    /// This is only a snapshot for diagnostics and tests: another thread may
+    /// lock or unlock right after we read it, so never use the result to decide
+    /// whether it is safe to touch the data. It uses `Relaxed` because it does
+    /// not synchronize with anything.
+    pub fn is_locked(&self) -> bool {
+        self.raw.is_locked()
+    }
+
+    /// Debug builds only: the source location that made the most recent
+    /// guard, whether or not it still holds the lock (`None` if nobody
+    /// locked it yet). The lock methods are `#[track_caller]`, so that's
+    /// the `lock_*` / `try_lock*` call in the caller's code; helpers like
+    /// `with_lock` report themselves. The re-entrant lock panic includes it.
+    #[cfg(debug_assertions)]
+    pub fn last_locked_at(&self) -> Option<&'static Location<'static>> {
+        let at = self.locked_at.load(core::sync::atomic::Ordering::Relaxed);
+        // Only ever set from `Location::caller()`, which is `'static`.
+        unsafe { at.as_ref() }
+    }
+
+    /// With the `history` feature: the threads and call sites (as in
+    /// `last_locked_at`) of the last `HISTORY_LEN` acquisitions, oldest
+    /// first. Never waits for the lock, so it can be called on one that
+    /// seems stuck to see who had it lately.
+    #[cfg(feature = "history")]
+    pub fn acquisition_history(
+        &self,
+    ) -> std::vec::Vec<(std::thread::ThreadId, &'static Location<'static>)> {
+        self.history.entries()
+    }
+
+    /// Spins until the lock is free, without taking it: "wait for whoever
+    /// holds it right now to finish". By the time this returns another thread
+    /// may already hold the lock again, so this is for coordination only and
+    /// says nothing about whether it's safe to touch the data.
+    pub fn wait_until_unlocked(&self) {
+        // `Acquire` so whatever the previous holder did happened-before us.
+        while self.raw.locked.load(Ordering::Acquire) {
+            spin_loop();
+            self.record_spin();
+        }
+        // Waited, but didn't acquire anything.
+        self.gave_up();
+    }
+
+    /// A cheap guess at whether the lock is hot, for deciding at a higher
+    /// level whether to shard or back off.
+    ///
+    /// With the `stats` feature it is `true` when waiters have spun more
+    /// often than the lock was acquired (over the lock's whole lifetime), or
+    /// when it is held right now. Without `stats` it is just `is_locked()`.
+    pub fn contention_hint(&self) -> bool {
+        #[cfg(feature = "stats")]
+        {
+            let stats = self.stats();
+            if stats.spins > stats.acquisitions {
+                return true;
+            }
+        }
+        self.is_locked()
+    }
+
+    /// `&mut self` already proves nobody else can touch the lock,
+    /// so we can hand out the value directly without any atomic ops.
+    /// Same idea as `std::sync::Mutex::get_mut`.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    /// Puts the lock back to unlocked and unpoisoned, e.g. before handing a
+    /// pooled lock to its next user, even if the last one leaked a guard.
+    /// The value is left as it is. Safe, unlike `force_unlock`: `&mut self`
+    /// means no guard, leaked or not, can still be in use.
+    pub fn reset(&mut self) {
+        self.raw.locked = AtomicBool::new(false);
+        self.poisoned = AtomicBool::new(false);
+        #[cfg(all(debug_assertions, feature = "std"))]
+        {
+            self.owner = core::sync::atomic::AtomicUsize::new(0);
+        }
+        #[cfg(debug_assertions)]
+        {
+            self.locked_at = core::sync::atomic::AtomicPtr::new(core::ptr::null_mut());
+        }
+        #[cfg(feature = "history")]
+        {
+            self.history = history::History::new();
+        }
+        self.guards_forgotten();
+    }
+
+    /// Raw pointer to the protected value, for FFI and other unsafe code that
+    /// does its own locking (e.g. around `unlock`/`force_unlock`).
+    ///
+    /// Getting the pointer is safe, using it is not: reading or writing
+    /// through it is only sound while this thread holds the lock (and then
+    /// not at the same time as through the guard), otherwise it is a data
+    /// race and undefined behavior. The pointer stays valid for as long as the
+    /// lock isn't moved or dropped.
+    pub fn as_mut_ptr(&self) -> *mut T {
+        self.value.get()
+    }
+
+    /// # Safety
+    ///
+    /// The &mut T from lock() must be gone!
+    /// (And no cheating by keeping reference to fields of that T around!)
+    /// unlock method only trans it to false.
+    #[cfg_attr(feature = "profiling", inline(never))]
+    #[cfg_attr(not(feature = "profiling"), inline)]
+    pub unsafe fn unlock(&self) {
+        self.unlock_with(raw::UNLOCK);
+    }
+
+    /// `unlock` with a caller-chosen ordering for the releasing store, the
+    /// counterpart of `lock_with_ordering` for hand-rolled protocols. E.g.
+    /// `SeqCst` also orders the release against the caller's other `SeqCst`
+    /// operations. Anything weaker than `Release` would let the next holder
+    /// miss our writes, so only `Release` and `SeqCst` are accepted
+    /// (checked with a `debug_assert!`).
+    ///
+    /// # Safety
+    ///
+    /// Same as `unlock`.
+    pub unsafe fn unlock_with(&self, ordering: Ordering) {
+        self.disown();
+        self.guards_forgotten();
+        self.release_with(ordering);
+    }
+
+    /// A guard for a lock that is already held some other way, e.g. by
+    /// C code across an FFI boundary, or since `new_locked`, and is now handed
+    /// back to Rust. Nothing is acquired: the guard simply takes over,
+    /// and dropping it releases the lock as usual.
+    ///
+    /// # Safety
+    ///
+    /// The lock must be held, by nobody else than the caller, and nothing
+    /// else may release it or touch the value while the guard lives. Calling
+    /// this on a free lock lets another thread lock it too, two guards then
+    /// race on the value.
+    pub unsafe fn guard_unchecked(&self) -> Guard<'_, T, B> {
+        Guard::new(self)
+    }
+
+    /// Releases the lock no matter what, for when a guard was leaked
+    /// (`mem::forget`, `Guard::leak`) and the lock has to be reclaimed.
+    ///
+    /// It unconditionally stores `false`; it does not know or check whether a
+    /// guard or a leaked `&mut T` still exists. If one does, the next locker
+    /// and that reference race on the data.
+    ///
+    /// # Safety
+    ///
+    /// There must be no live guard for this lock, and nothing derived from
+    /// one (a leaked `&mut T`, references into it) may be used afterwards.
+    pub unsafe fn force_unlock(&self) {
+        self.disown();
+        self.guards_forgotten();
+        self.release();
+    }
+
+    /// `unlock` for catching misuse: only releases the lock if it is actually
+    /// held, and returns `UnlockError::NotLocked` instead of silently storing
+    /// `false` again when it isn't.
+    ///
+    /// # Safety
+    ///
+    /// Same as `unlock`. The check only notices a lock that is free; it can't
+    /// tell whether the current holder is the caller.
+    pub unsafe fn try_unlock(&self) -> Result<(), UnlockError> {
+        self.disown();
+        self.guards_forgotten();
+        self.raw
+            .locked
+            .compare_exchange(
+                true,
+                false,
+                raw::UNLOCK,
+                core::sync::atomic::Ordering::Relaxed,
+            )
+            .map_err(|_| UnlockError::NotLocked)?;
+        #[cfg(feature = "park")]
+        self.waiters.wake_one();
+        Ok(())
+    }
+}
+
+/// What needs `T` by value, so not for unsized `T`.
+impl<T, const B: usize> SpinLock<T, B> {
+    /// Consumes the lock and gives back the protected value.
+    /// Taking `self` by value means no guard can still be alive.
+    ///
+    /// Like `replace`, a plain move whatever `T` is: nothing is dropped on
+    /// the way, so there is no cheaper path for `Copy` or `!needs_drop` types.
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    /// Puts `value` in and returns the old value, under the lock.
+    pub fn replace(&self, value: T) -> T {
+        core::mem::replace(&mut *self.lock_with_cas(), value)
+    }
+
+    /// Replaces the value with `f(old)`, under the lock. For a transform
+    /// that only needs `&mut T`, `update` does the same without the bound.
+    ///
+    /// `f` gets the old value by move, so while it runs the lock holds
+    /// `T::default()` in its place. That is also what stays behind if `f`
+    /// panics (and the lock gets poisoned, with `std`), rather than a value
+    /// that has already been moved out.
+    pub fn replace_with(&self, f: impl FnOnce(T) -> T)
+    where
+        T: Default,
+    {
+        let mut guard = self.lock_with_cas();
+        let old = core::mem::take(&mut *guard);
+        *guard = f(old);
+    }
+
+    /// A clone of the value as it is right now, taken under the lock, which
+    /// is released again before this returns. The copy is consistent (no
+    /// writer was halfway through), but it is a point-in-time snapshot:
+    /// later changes to the lock don't show up in it, and the other way round.
+    pub fn peek(&self) -> T
+    where
+        T: Clone,
+    {
+        self.lock_with_cas().clone()
+    }
+
+    /// Swaps the values of two locks, locking both in address order (see
+    /// `lock!`). Swapping a lock with itself does nothing.
+    pub fn swap(&self, other: &SpinLock<T, B>) {
+        if core::ptr::eq(self, other) {
+            return;
+        }
+        let (mut a, mut b) = lock!(self, other);
+        core::mem::swap(&mut *a, &mut *b);
+    }
+}
+
+impl<T: ?Sized + Sync, const B: usize> SpinLock<T, B> {
+    /// Takes the lock like `lock_with_cas`, but the guard only derefs to `&T`,
+    /// which makes read-only intent explicit at the call site:
+    ///
+    /// ```compile_fail
+    /// let lock = my_spin_lock::SpinLock::new(0);
+    /// *lock.read_shared() += 1;
+    /// ```
+    ///
+    /// It is still the exclusive lock, so readers wait for each other. Use a
+    /// `RwSpinLock` if they should actually run at the same time.
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn read_shared(&self) -> impl Deref<Target = T> + '_ {
+        struct ReadOnly<'a, T: ?Sized, const B: usize>(Guard<'a, T, B>);
+
+        impl<T: ?Sized, const B: usize> Deref for ReadOnly<'_, T, B> {
+            type Target = T;
+            fn deref(&self) -> &T {
+                &self.0
+            }
+        }
+
+        ReadOnly(self.lock_with_cas())
+    }
+}
+
+/// Shortcuts for a lock around an optional resource, e.g. a connection
+/// that is created lazily and handed off to whoever claims it first.
+impl<T, const B: usize> SpinLock<Option<T>, B> {
+    /// Moves the value out, leaving `None`. When several threads race for it
+    /// exactly one gets `Some`.
+    pub fn take(&self) -> Option<T> {
+        self.lock_with_cas().take()
+    }
+
+    /// Advisory like `is_locked`: another thread may `take` the value right
+    /// after this returns `true`.
+    pub fn is_some(&self) -> bool {
+        self.lock_with_cas().is_some()
+    }
+
+    /// Fills in the value with `f` if there is none yet (under the lock, so
+    /// `f` runs at most once per empty slot), and returns a guard to it.
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn get_or_insert_with(&self, f: impl FnOnce() -> T) -> MappedGuard<'_, Option<T>, T, B> {
+        Guard::map(self.lock_with_cas(), |v| v.get_or_insert_with(f))
+    }
+}
+
+/// One-line versions of the usual `lock_with_cas().push(..)` and friends,
+/// each a critical section of its own.
+#[cfg(feature = "std")]
+impl<T, const B: usize> SpinLock<std::vec::Vec<T>, B> {
+    pub fn push(&self, value: T) {
+        self.lock_with_cas().push(value);
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        self.lock_with_cas().pop()
+    }
+
+    /// A snapshot: other threads may push or pop right after.
+    pub fn len(&self) -> usize {
+        self.lock_with_cas().len()
+    }
+
+    /// A snapshot like `len`.
+    pub fn is_empty(&self) -> bool {
+        self.lock_with_cas().is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.lock_with_cas().clear();
+    }
+
+    /// Growing the vec may allocate, which is better done before a burst of
+    /// `push`es than while holding the lock in the middle of one.
+    pub fn reserve(&self, additional: usize) {
+        self.lock_with_cas().reserve(additional);
+    }
+
+    pub fn shrink_to_fit(&self) {
+        self.lock_with_cas().shrink_to_fit();
+    }
+}
+
+macro_rules! impl_integer_ops {
+    ($($int:ty)*) => {$(
+        /// Atomic-style methods for a lock around a plain integer, for when
+        /// the number has to move together with other state under the same
+        /// lock, or just doesn't have an atomic of its own. Like the atomics,
+        /// `fetch_add` / `fetch_sub` wrap on overflow and return the previous
+        /// value.
+        impl<const B: usize> SpinLock<$int, B> {
+            pub fn fetch_add(&self, n: $int) -> $int {
+                let mut guard = self.lock_with_cas();
+                let prev = *guard;
+                *guard = prev.wrapping_add(n);
+                prev
+            }
+
+            pub fn fetch_sub(&self, n: $int) -> $int {
+                let mut guard = self.lock_with_cas();
+                let prev = *guard;
+                *guard = prev.wrapping_sub(n);
+                prev
+            }
+
+            /// A snapshot, like `peek`.
+            pub fn load(&self) -> $int {
+                *self.lock_with_cas()
+            }
+
+            pub fn store(&self, value: $int) {
+                *self.lock_with_cas() = value;
+            }
+        }
+    )*};
+}
+
+impl_integer_ops!(u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize);
+
+impl<A, C, const B: usize> SpinLock<(A, C), B> {
+    /// Runs `f` on both values of the pair in one critical section, see
+    /// `Guard::fields`. Unlike `split`, the two stay under one lock.
+    pub fn with_fields<R>(&self, f: impl FnOnce(&mut A, &mut C) -> R) -> R {
+        let mut guard = self.lock_with_cas();
+        let (a, c) = Guard::fields(&mut guard);
+        f(a, c)
+    }
+
+    /// Breaks a lock around a pair into one lock per field, so the two can
+    /// be locked independently from then on. Consuming `self` rules out live
+    /// guards. Both halves keep the poison flag and `max_spins` (or being
+    /// autotuned), but not the name or level, which belonged to the pair.
+    pub fn split(self) -> (SpinLock<A, B>, SpinLock<C, B>) {
+        let poisoned = self.is_poisoned();
+        #[cfg(feature = "std")]
+        let (max_spins, autotuned) = (self.max_spins, self.autotuned);
+        let (a, c) = self.into_inner();
+        let mut a = SpinLock::with_spin_budget(a);
+        let mut c = SpinLock::with_spin_budget(c);
+        a.poisoned = AtomicBool::new(poisoned);
+        c.poisoned = AtomicBool::new(poisoned);
+        #[cfg(feature = "std")]
+        {
+            a.max_spins = max_spins;
+            c.max_spins = max_spins;
+            a.autotuned = autotuned;
+            c.autotuned = autotuned;
+        }
+        (a, c)
+    }
+}
+
+/// Same as `std::sync::Mutex`: an unlocked `T::default()`.
+impl<T: Default, const B: usize> Default for SpinLock<T, B> {
+    fn default() -> Self {
+        Self::with_spin_budget(T::default())
+    }
+}
+
+/// Lets you write `let lock: SpinLock<_> = value.into();`.
+impl<T, const B: usize> From<T> for SpinLock<T, B> {
+    fn from(value: T) -> Self {
+        Self::with_spin_budget(value)
+    }
+}
+
+/// Clones the value into a fresh, unlocked lock. This briefly takes the
+/// source lock (spinning if someone holds it) so the copy is consistent.
+/// Poison is not carried over.
+impl<T: Clone, const B: usize> Clone for SpinLock<T, B> {
+    fn clone(&self) -> Self {
+        Self::with_spin_budget(self.lock_with_cas().clone())
+    }
+}
+
+/// Compares the values, locking both sides in address order (like `lock!`)
+/// so `a == b` and `b == a` on two threads can't deadlock. The locks are taken
+/// one after the other, so the result is only a snapshot: concurrent writers
+/// may change either value right before or after.
+impl<T: ?Sized + PartialEq, const B: usize> PartialEq for SpinLock<T, B> {
+    fn eq(&self, other: &Self) -> bool {
+        if core::ptr::eq(self, other) {
+            // `lock!` would panic here; still compare, `T` may not be reflexive.
+            let guard = self.lock_with_cas();
+            let value: &T = &guard;
+            return value.eq(value);
+        }
+        let (a, b) = lock!(self, other);
+        *a == *b
+    }
+}
+
+impl<T: ?Sized + Eq, const B: usize> Eq for SpinLock<T, B> {}
+
+/// Hashes the value, holding the lock just while doing so, which makes it
+/// consistent with `PartialEq`. Like any key with interior mutability, a lock
+/// inside a `HashSet`/`HashMap` must not have its value changed afterwards:
+/// the collection would look for it under the old hash.
+impl<T: ?Sized + Hash, const B: usize> Hash for SpinLock<T, B> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.lock_with_cas().hash(state);
+    }
+}
+
+/// Mirrors `std::sync::Mutex`: prints the value if the lock is free right now,
+/// otherwise a `<locked>` placeholder. It never waits for the lock.
+impl<T: ?Sized + fmt::Debug, const B: usize> fmt::Debug for SpinLock<T, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Released even if `T`'s `Debug` panics.
+        struct Peek<'a, T: ?Sized, const B: usize>(&'a SpinLock<T, B>);
+        impl<T: ?Sized, const B: usize> Drop for Peek<'_, T, B> {
+            fn drop(&mut self) {
+                unsafe { self.0.release() }
+            }
+        }
+
+        let mut d = f.debug_struct("SpinLock");
+        // Straight through `raw`, not `try_lock`: looking at the lock isn't
+        // an acquisition, so it mustn't show up in the stats, the history,
+        // `last_locked_at` or the hierarchy.
+        if self.raw.try_lock() {
+            let peek = Peek(self);
+            d.field("data", &unsafe { &*peek.0.value.get() });
+        } else {
+            d.field("data", &format_args!("<locked>"));
+        }
+        d.field("poisoned", &self.is_poisoned());
+        d.finish_non_exhaustive()
+    }
+}
+
+/// Puts every item behind its own `SpinLock`, e.g. to shard data so threads
+/// working on different elements don't contend.
+#[cfg(feature = "std")]
+pub fn locked_vec<T, I: IntoIterator<Item = T>>(iter: I) -> std::vec::Vec<SpinLock<T>> {
+    iter.into_iter().map(SpinLock::new).collect()
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn it_works_with_swap() {
+        let x = SpinLock::new(Vec::new());
+        thread::scope(|s| {
+            s.spawn(|| x.lock_with_swap().push(42));
+            s.spawn(|| {
+                let mut g = x.lock_with_swap();
+                g.push(21);
+                g.push(21);
+            });
+        });
+        let g = x.lock_with_swap();
+        assert!(g.as_slice() == [42, 21, 21] || g.as_slice() == [21, 21, 42]);
+    }
+
+    #[test]
+    fn it_works_with_cas() {
+        let x = SpinLock::new(Vec::new());
+        thread::scope(|s| {
+            s.spawn(|| x.lock_with_cas().push(1));
+            s.spawn(|| {
+                let mut g = x.lock_with_cas();
+                g.push(2);
+                g.push(2);
+            });
+        });
+        let g = x.lock_with_cas();
+        assert!(g.as_slice() == [1, 2, 2] || g.as_slice() == [2, 2, 1]);
+    }
+
+    #[test]
+    fn it_works_with_lock() {
+        let x = SpinLock::new(Vec::new());
+        thread::scope(|s| {
+            s.spawn(|| x.lock().push(1));
+            s.spawn(|| {
+                let mut g = x.lock();
+                g.push(2);
+                g.push(2);
+            });
+        });
+        let g = x.lock();
+        assert!(x.is_locked());
+        assert!(g.as_slice() == [1, 2, 2] || g.as_slice() == [2, 2, 1]);
+    }
+
+    /// Whichever of `default-cas` / `default-swap` is on, e.g. run with
+    /// `--no-default-features --features std,default-swap` for the latter.
+    #[test]
+    fn lock_under_contention() {
+        let x = SpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..2_000 {
+                        *x.lock() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(x.into_inner(), 8_000);
+    }
+
+    #[test]
+    fn backoff_under_contention() {
+        let x = SpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for i in 0..10_000 {
+                        if i % 2 == 0 {
+                            *x.lock_with_backoff() += 1;
+                        } else {
+                            *x.lock() += 1;
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(x.into_inner(), 80_000);
+    }
+
+    #[test]
+    fn lock_with_uncontended_never_snoozes() {
+        let x = SpinLock::new(0);
+        let mut backoff = Backoff::new();
+        for _ in 0..3 {
+            *x.lock_with(&mut backoff) += 1;
+            assert_eq!(backoff.step(), 0);
+        }
+        assert_eq!(x.into_inner(), 3);
+    }
+
+    #[test]
+    fn lock_with_contended_snoozes() {
+        let x = SpinLock::new(0);
+        let g = x.lock_with_cas();
+        thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                let mut backoff = Backoff::new();
+                *x.lock_with(&mut backoff) += 1;
+                backoff.step()
+            });
+            thread::sleep(std::time::Duration::from_millis(20));
+            drop(g);
+            assert!(waiter.join().unwrap() > 0);
+        });
+        assert_eq!(x.into_inner(), 1);
+    }
+
+    #[test]
+    fn with_lock_returns_closure_result() {
+        let x = SpinLock::new(vec![1, 2, 3]);
+        let sum: i32 = x.with_lock(|v| {
+            v.push(4);
+            v.iter().sum()
+        });
+        assert_eq!(sum, 10);
+        assert!(!x.is_locked());
+    }
+
+    #[test]
+    fn with_lock_releases_on_panic() {
+        let x = SpinLock::new(0);
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            x.with_lock(|v| {
+                *v += 1;
+                panic!("boom");
+            })
+        }));
+        assert!(r.is_err());
+        assert_eq!(*x.try_lock().unwrap(), 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn with_lock_catch_contains_panic() {
+        let x = SpinLock::new(0);
+        assert_eq!(x.with_lock_catch(|v| *v + 1).unwrap(), 1);
+        let err = x
+            .with_lock_catch(|v| {
+                *v += 1;
+                panic!("plugin failed");
+            })
+            .unwrap_err();
+        assert_eq!(err.downcast_ref::<&str>(), Some(&"plugin failed"));
+        assert!(!x.is_locked());
+        assert!(x.is_poisoned());
+        assert_eq!(*x.lock_with_cas(), 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn panic_while_locked_poisons() {
+        let x = SpinLock::new(0);
+        thread::scope(|s| {
+            let h = s.spawn(|| {
+                let _g = x.lock_with_cas();
+                panic!("poison it");
+            });
+            assert!(h.join().is_err());
+        });
+        assert!(x.is_poisoned());
+        let err = x
+            .lock_with_swap_checked()
+            .err()
+            .expect("lock should be poisoned");
+        assert_eq!(*err.into_inner(), 0);
+
+        x.clear_poison();
+        assert!(!x.is_poisoned());
+        assert!(x.lock_with_cas_checked().is_ok());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn lock_or_recover_repairs_once() {
+        let x = SpinLock::new(vec![1]);
+        thread::scope(|s| {
+            let h = s.spawn(|| {
+                let mut g = x.lock_with_cas();
+                g.push(-1);
+                panic!("left a bad value behind");
+            });
+            assert!(h.join().is_err());
+        });
+        let g = x.lock_or_recover(|v| v.retain(|&n| n >= 0));
+        assert_eq!(*g, [1]);
+        assert!(!x.is_poisoned());
+        drop(g);
+        // Nothing to recover any more.
+        x.lock_or_recover(|_| unreachable!()).push(2);
+        assert_eq!(*x.lock_with_cas_checked().unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn lock_ttas_under_contention() {
+        let x = SpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..16 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *x.lock_ttas() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(x.into_inner(), 16 * 1000);
+    }
+
+    #[test]
+    fn lock_with_jitter_under_contention() {
+        let x = SpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..16 {
+                s.spawn(|| {
+                    for _ in 0..500 {
+                        *x.lock_with_jitter() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(x.into_inner(), 16 * 500);
+    }
+
+    #[test]
+    fn lock_relax_with_each_relaxer() {
+        struct Counting(u64);
+        impl Relax for Counting {
+            fn relax(&mut self) {
+                self.0 += 1;
+                spin_loop();
+            }
+        }
+
+        fn hammer<R: Relax>(make: impl Fn() -> R + Sync) {
+            let x = SpinLock::new(0);
+            thread::scope(|s| {
+                for _ in 0..4 {
+                    s.spawn(|| {
+                        let mut relax = make();
+                        for _ in 0..500 {
+                            *x.lock_relax(&mut relax) += 1;
+                        }
+                    });
+                }
+            });
+            assert_eq!(x.into_inner(), 4 * 500);
+        }
+        hammer(|| SpinRelax);
+        #[cfg(feature = "std")]
+        hammer(|| YieldRelax);
+        hammer(|| NoopRelax);
+        hammer(Backoff::new);
+        hammer(|| Counting(0));
+
+        // An uncontended lock never waits.
+        let x = SpinLock::new(());
+        let mut counting = Counting(0);
+        drop(x.lock_relax(&mut counting));
+        assert_eq!(counting.0, 0);
+        // A dyn relaxer works as well.
+        let relax: &mut dyn Relax = &mut counting;
+        drop(x.lock_relax(relax));
+    }
+
+    #[test]
+    fn new_with_calls_closure_once() {
+        let calls = core::cell::Cell::new(0);
+        let x = SpinLock::new_with(|| {
+            calls.set(calls.get() + 1);
+            vec![1, 2, 3]
+        });
+        assert_eq!(calls.get(), 1);
+        assert!(!x.is_locked());
+        assert_eq!(x.lock_with_cas().len(), 3);
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn new_locked_starts_held() {
+        static READY: SpinLock<Vec<u8>> = SpinLock::new_locked(Vec::new());
+        assert!(READY.is_locked());
+        assert!(READY.try_lock().is_none());
+        // The creator still may set things up before opening the lock.
+        unsafe {
+            (*READY.as_mut_ptr()).push(1);
+            READY.unlock();
+        }
+        assert_eq!(*READY.try_lock().unwrap(), [1]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn lock_checked_reports_poison() {
+        let x = SpinLock::new(0);
+        assert_eq!(*x.lock_checked().unwrap(), 0);
+        thread::scope(|s| {
+            let h = s.spawn(|| {
+                let _g = x.lock_with_cas();
+                panic!("poison it");
+            });
+            assert!(h.join().is_err());
+        });
+        match x.lock_checked() {
+            Err(LockError::Poisoned(err)) => assert_eq!(*err.into_inner(), 0),
+            Err(other) => panic!("expected poison, got {other:?}"),
+            Ok(_) => panic!("expected poison"),
+        };
+    }
+
+    #[cfg(all(debug_assertions, feature = "std"))]
+    #[test]
+    fn lock_checked_reports_would_deadlock() {
+        let x = SpinLock::new(0);
+        let g = x.lock_checked().unwrap();
+        assert!(matches!(x.lock_checked(), Err(LockError::WouldDeadlock)));
+        drop(g);
+        assert!(x.lock_checked().is_ok());
+
+        let high = SpinLock::with_level((), 2);
+        let low = SpinLock::with_level((), 1);
+        let _l = low.lock_checked().unwrap();
+        assert!(matches!(high.lock_checked(), Err(LockError::WouldDeadlock)));
+    }
+
+    #[test]
+    fn every_release_path_publishes_writes() {
+        // The value written by one thread must be what the next holder sees,
+        // whichever way the first one let go of the lock.
+        fn handoff(release: fn(&SpinLock<Vec<u32>>, Guard<'_, Vec<u32>>)) {
+            let x = SpinLock::new(Vec::new());
+            for round in 0..50 {
+                thread::scope(|s| {
+                    s.spawn(|| {
+                        let mut g = x.lock_with_cas();
+                        g.push(round);
+                        release(&x, g);
+                    });
+                });
+                assert_eq!(x.lock_with_cas().last(), Some(&round));
+            }
+            assert!(!x.is_locked());
+        }
+        handoff(|_, g| drop(g));
+        handoff(|x, g| {
+            core::mem::forget(g);
+            unsafe { x.unlock() };
+        });
+        handoff(|x, g| {
+            core::mem::forget(g);
+            unsafe { x.unlock_with(Ordering::SeqCst) };
+        });
+        handoff(|x, g| {
+            Guard::leak(g);
+            unsafe { x.force_unlock() };
+        });
+        handoff(|x, g| {
+            core::mem::forget(g);
+            unsafe { x.try_unlock() }.unwrap();
+        });
+    }
+
+    #[test]
+    fn unsized_values() {
+        let array = SpinLock::new([1, 2, 3]);
+        let slice: &SpinLock<[i32]> = &array;
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| slice.lock_with_cas().iter_mut().for_each(|v| *v *= 2));
+            }
+        });
+        assert_eq!(*slice.lock_with_cas(), [4, 8, 12]);
+        let mut tail = Guard::map(slice.lock_with_cas(), |v| &mut v[1..]);
+        tail[0] = 0;
+        drop(tail);
+        assert_eq!(array.into_inner(), [4, 0, 12]);
+
+        let text: Box<SpinLock<dyn fmt::Write>> = Box::new(SpinLock::new(String::new()));
+        write!(text.lock_with_cas(), "{}", 42).unwrap();
+        assert!(!text.is_locked());
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn guard_count_stays_balanced() {
+        let x = SpinLock::new(0);
+        let live = || x.guards.load(Ordering::Relaxed);
+        for _ in 0..3 {
+            let g = x.lock_with_cas();
+            assert_eq!(live(), 1);
+            drop(g);
+            assert_eq!(live(), 0);
+        }
+        drop(x.try_lock());
+        drop(Guard::map(x.lock_with_swap(), |v| v));
+        assert_eq!(live(), 0);
+        Guard::leak(x.lock_with_cas());
+        unsafe { x.force_unlock() };
+        assert_eq!(live(), 0);
+        // Reached zero again, so this lock must not trip the assertion.
+        drop(x.lock_with_cas());
+    }
+
+    #[test]
+    fn lock_if_unlocked_and_checks_both() {
+        let x = SpinLock::new(3);
+        assert_eq!(x.lock_if_unlocked_and(|v| *v > 5).map(|g| *g), None);
+        assert!(!x.is_locked());
+        let mut g = x.lock_if_unlocked_and(|v| *v == 3).unwrap();
+        thread::scope(|s| {
+            s.spawn(|| assert!(x.lock_if_unlocked_and(|_| unreachable!()).is_none()));
+        });
+        *g = 10;
+        drop(g);
+        assert_eq!(*x.lock_if_unlocked_and(|v| *v > 5).unwrap(), 10);
+    }
+
+    #[test]
+    fn try_lock_fails_while_held() {
+        let x = SpinLock::new(0);
+        let g = x.lock_with_cas();
+        thread::scope(|s| {
+            s.spawn(|| assert!(x.try_lock().is_none()));
+        });
+        drop(g);
+        let mut g = x
+            .try_lock()
+            .expect("lock should be free after the guard drops");
+        *g += 1;
+        assert_eq!(*g, 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn try_lock_for_times_out() {
+        let x = SpinLock::new(0);
+        let held = AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                let _g = x.lock_with_cas();
+                held.store(true, std::sync::atomic::Ordering::Release);
+                thread::sleep(Duration::from_millis(200));
+            });
+            while !held.load(std::sync::atomic::Ordering::Acquire) {
+                thread::yield_now();
+            }
+            let start = Instant::now();
+            assert!(x.try_lock_for(Duration::from_millis(20)).is_none());
+            assert!(start.elapsed() >= Duration::from_millis(20));
+        });
+        assert!(x.try_lock_for(Duration::from_millis(20)).is_some());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn try_lock_until_past_deadline_is_immediate() {
+        let x = SpinLock::new(0);
+        let past = Instant::now();
+        thread::sleep(Duration::from_millis(1));
+        let g = x.lock_with_cas();
+        thread::scope(|s| {
+            s.spawn(|| assert!(x.try_lock_until(past).is_none()));
+        });
+        drop(g);
+        // Still gets its single attempt when the lock is free.
+        assert!(x.try_lock_until(past).is_some());
+    }
+
+    #[test]
+    fn lock_cancellable_gives_up_when_cancelled() {
+        use core::sync::atomic::AtomicBool;
+
+        let x = SpinLock::new(0);
+        let cancel = AtomicBool::new(false);
+        let g = x.lock_with_cas();
+        thread::scope(|s| {
+            let waiter = s.spawn(|| x.lock_cancellable(&cancel).is_none());
+            // The watcher decides the holder is stuck.
+            s.spawn(|| {
+                thread::sleep(std::time::Duration::from_millis(10));
+                cancel.store(true, Ordering::Relaxed);
+            });
+            assert!(waiter.join().unwrap());
+        });
+        drop(g);
+        // Cancelled or not, a free lock is taken.
+        *x.lock_cancellable(&cancel).unwrap() += 1;
+        cancel.store(false, Ordering::Relaxed);
+        assert_eq!(*x.lock_cancellable(&cancel).unwrap(), 1);
+    }
+
+    #[test]
+    fn is_locked_follows_guard() {
+        let x = SpinLock::new(());
+        assert!(!x.is_locked());
+        let g = x.lock_with_swap();
+        assert!(x.is_locked());
+        drop(g);
+        assert!(!x.is_locked());
+        let g = x.lock();
+        assert!(x.is_locked());
+        drop(g);
+        assert!(!x.is_locked());
+    }
+
+    #[test]
+    fn reset_recycles_a_leaked_lock() {
+        let mut x = SpinLock::new(5);
+        core::mem::forget(x.lock_with_cas());
+        assert!(x.try_lock().is_none());
+        x.reset();
+        assert!(!x.is_locked());
+        assert_eq!(*x.lock_with_cas(), 5);
+        assert_eq!(x.into_inner(), 5);
+    }
+
+    #[test]
+    fn get_mut_without_locking() {
+        let mut x = SpinLock::new(Vec::new());
+        x.get_mut().push(7);
+        x.get_mut().push(8);
+        assert_eq!(x.lock_with_cas().as_slice(), [7, 8]);
+    }
+
+    #[test]
+    fn into_inner_returns_value() {
+        let x = SpinLock::new(String::from("spin"));
+        let mut g = x.lock_with_swap();
+        g.push_str("lock");
+        drop(g);
+        assert_eq!(x.into_inner(), "spinlock");
+    }
+
+    #[test]
+    fn default_is_unlocked_default_value() {
+        let x = SpinLock::<Vec<u8>>::default();
+        assert!(!x.is_locked());
+        assert!(x.lock_with_cas().is_empty());
+    }
+
+    #[test]
+    fn from_value() {
+        let x: SpinLock<i32> = 42.into();
+        assert_eq!(*x.lock_with_swap(), 42);
+    }
+
+    #[test]
+    fn debug_shows_value_or_locked() {
+        let x = SpinLock::new(vec![1, 2]);
+        assert!(format!("{x:?}").contains("[1, 2]"));
+        let g = x.lock_with_cas();
+        let s = format!("{x:?}");
+        assert!(s.contains("<locked>"), "{s}");
+        drop(g);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn debug_is_not_an_acquisition() {
+        let x = SpinLock::new(1);
+        let (g, line) = (x.lock_with_cas(), line!());
+        drop(g);
+        #[cfg(feature = "stats")]
+        let before = x.stats().acquisitions;
+        assert!(format!("{x:?}").contains('1'));
+        assert_eq!(x.last_locked_at().unwrap().line(), line);
+        #[cfg(feature = "stats")]
+        assert_eq!(x.stats().acquisitions, before);
+        #[cfg(feature = "history")]
+        assert_eq!(x.acquisition_history().len(), 1);
+        assert!(!x.is_locked());
+    }
+
+    #[test]
+    fn map_guard_to_field() {
+        let x = SpinLock::new((1, String::from("a")));
+        let mut s = Guard::map(x.lock_with_cas(), |v| &mut v.1);
+        s.push('b');
+        assert!(x.is_locked());
+        drop(s);
+        assert!(!x.is_locked());
+        assert_eq!(*x.lock_with_cas(), (1, String::from("ab")));
+    }
+
+    #[test]
+    fn explicit_getters() {
+        let x = SpinLock::new(vec![1, 2]);
+        let mut g = x.lock_with_cas();
+        Guard::get_mut(&mut g).push(3);
+        assert_eq!(Guard::get(&g), &[1, 2, 3]);
+        // `Vec::get` is still reachable through the guard.
+        assert_eq!(g.get(1), Some(&2));
+    }
+
+    #[test]
+    fn try_map_projects_or_gives_guard_back() {
+        let x = SpinLock::new(vec![1, 2]);
+        let mut first = Guard::try_map(x.lock_with_cas(), |v| v.first_mut())
+            .ok()
+            .unwrap();
+        *first = 10;
+        drop(first);
+        assert!(!x.is_locked());
+
+        let mut g = match Guard::try_map(x.lock_with_cas(), |v| v.get_mut(5)) {
+            Ok(_) => panic!("there is no element 5"),
+            Err(g) => g,
+        };
+        // Still locked, and the guard works as before.
+        assert!(x.is_locked());
+        g.push(3);
+        drop(g);
+        assert_eq!(*x.lock_with_cas(), [10, 2, 3]);
+    }
+
+    #[test]
+    fn mapped_guard_map_chains() {
+        let x = SpinLock::new((0, (String::new(), 1)));
+        let outer = Guard::map(x.lock_with_cas(), |v| &mut v.1);
+        let mut inner = MappedGuard::map(outer, |v| &mut v.0);
+        inner.push('a');
+        assert!(x.is_locked());
+        drop(inner);
+        assert!(!x.is_locked());
+        assert_eq!(x.lock_with_cas().1 .0, "a");
+    }
+
+    #[test]
+    fn lock_with_ordering_under_contention() {
+        let x = SpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *x.lock_with_ordering(Ordering::Acquire, Ordering::Relaxed) += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(x.into_inner(), 4000);
+    }
+
+    #[test]
+    fn seqcst_unlock_under_contention() {
+        let x = SpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        let mut g = x.lock_with_ordering(Ordering::SeqCst, Ordering::Relaxed);
+                        *g += 1;
+                        core::mem::forget(g);
+                        unsafe { x.unlock_with(Ordering::SeqCst) };
+                    }
+                });
+            }
+        });
+        assert_eq!(x.into_inner(), 4000);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be a Release store")]
+    #[cfg(debug_assertions)]
+    fn unlock_with_rejects_relaxed() {
+        let x = SpinLock::new(0);
+        core::mem::forget(x.lock_with_cas());
+        unsafe { x.unlock_with(Ordering::Relaxed) };
+    }
+
+    #[test]
+    #[should_panic(expected = "weaker than Acquire")]
+    #[cfg(debug_assertions)]
+    fn lock_with_ordering_rejects_relaxed() {
+        let x = SpinLock::new(0);
+        let _g = x.lock_with_ordering(Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn stats_count_acquisitions_and_spins() {
+        let x = SpinLock::new(0);
+        for _ in 0..5 {
+            *x.lock_with_cas() += 1;
+        }
+        assert_eq!(
+            x.stats(),
+            LockStats {
+                acquisitions: 5,
+                spins: 0
+            }
+        );
+
+        let g = x.lock_with_cas();
+        thread::scope(|s| {
+            s.spawn(|| *x.lock_with_cas() += 1);
+            thread::sleep(std::time::Duration::from_millis(20));
+            drop(g);
+        });
+        let stats = x.stats();
+        assert_eq!(stats.acquisitions, 7);
+        assert!(stats.spins > 0);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn export_metrics_prometheus_lines() {
+        let x = SpinLock::new(0);
+        for _ in 0..3 {
+            *x.lock_with_cas() += 1;
+        }
+        assert_eq!(
+            x.export_metrics("jobs"),
+            "spinlock_acquisitions_total{name=\"jobs\"} 3\n\
+             spinlock_spins_total{name=\"jobs\"} 0\n"
+        );
+        assert!(x
+            .export_metrics("a\"b")
+            .starts_with("spinlock_acquisitions_total{name=\"a\\\"b\"} 3\n"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn adaptive_with_more_threads_than_cores() {
+        let threads = thread::available_parallelism().map_or(4, |n| n.get()) * 4;
+        let x = SpinLock::new(0);
+        let start = Instant::now();
+        thread::scope(|s| {
+            for _ in 0..threads {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *x.lock_adaptive() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(x.into_inner(), threads * 1000);
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn autotuned_under_contention() {
+        let x = SpinLock::new_autotuned(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *x.lock_adaptive() += 1;
+                    }
+                });
+            }
+        });
+        // Whatever was measured, every autotuned lock agrees on it.
+        let tuned = x.spins_before_yield();
+        assert_eq!(SpinLock::new_autotuned(()).spins_before_yield(), tuned);
+        assert_eq!(
+            SpinLock::new(()).spins_before_yield(),
+            SpinLock::<()>::ADAPTIVE_SPINS
+        );
+        let (a, _) = SpinLock::new_autotuned((1, 2)).split();
+        assert_eq!(a.spins_before_yield(), tuned);
+        assert_eq!(x.into_inner(), 4000);
+    }
+
+    #[cfg(all(debug_assertions, feature = "std"))]
+    #[test]
+    #[should_panic(expected = "re-entrant lock on non-reentrant SpinLock")]
+    fn relock_on_same_thread_panics_in_debug() {
+        let x = SpinLock::new(0);
+        let _g = x.lock_with_cas();
+        let _g2 = x.lock_with_swap();
+    }
+
+    #[cfg(all(debug_assertions, feature = "std"))]
+    #[test]
+    fn relock_panic_says_where_the_lock_is_held() {
+        let x = SpinLock::new(0);
+        let (_g, line) = (x.lock(), line!());
+        let relock = std::panic::AssertUnwindSafe(|| drop(x.lock_with_cas()));
+        let msg = std::panic::catch_unwind(relock)
+            .unwrap_err()
+            .downcast::<String>()
+            .unwrap();
+        assert!(
+            msg.contains(&format!("held since {}:{line}:", file!())),
+            "{msg}"
+        );
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn wrapping_lock_methods_report_their_caller() {
+        let x = SpinLock::new(0);
+        let here = |line| (file!(), line);
+        let at = |x: &SpinLock<i32>| {
+            let at = x.last_locked_at().unwrap();
+            (at.file(), at.line())
+        };
+        let (g, line) = (x.lock_or_recover(|_| ()), line!());
+        drop(g);
+        assert_eq!(at(&x), here(line));
+        let (g, line) = (x.borrow_mut(), line!());
+        drop(g);
+        assert_eq!(at(&x), here(line));
+        let (g, line) = (x.lock_with_on_unlock(|| ()), line!());
+        drop(g);
+        assert_eq!(at(&x), here(line));
+        let cancel = core::sync::atomic::AtomicBool::new(false);
+        let (g, line) = (x.lock_cancellable(&cancel), line!());
+        drop(g);
+        assert_eq!(at(&x), here(line));
+        let (g, line) = (x.lock_if_unlocked_and(|_| true), line!());
+        drop(g);
+        assert_eq!(at(&x), here(line));
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn last_locked_at_is_the_call_site() {
+        let x = SpinLock::new(0);
+        assert!(x.last_locked_at().is_none());
+        let (g, line) = (x.lock_with_cas(), line!());
+        let at = x.last_locked_at().unwrap();
+        assert_eq!((at.file(), at.line()), (file!(), line));
+        drop(g);
+        // Still there once released, until the next acquisition.
+        assert_eq!(x.last_locked_at().unwrap().line(), line);
+        let (g, line) = (x.try_lock().unwrap(), line!());
+        assert_eq!(x.last_locked_at().unwrap().line(), line);
+        #[cfg(feature = "std")]
+        {
+            let relock = std::panic::AssertUnwindSafe(|| drop(x.lock()));
+            let msg = std::panic::catch_unwind(relock)
+                .unwrap_err()
+                .downcast::<String>()
+                .unwrap();
+            assert!(
+                msg.contains(&format!("held since {}:{line}:", file!())),
+                "{msg}"
+            );
+        }
+        drop(g);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn arc_guard_sent_away_is_not_a_self_deadlock() {
+        let x = std::sync::Arc::new(SpinLock::new(0));
+        let g = x.lock_arc();
+        thread::scope(|s| {
+            s.spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                drop(g);
+            });
+            // We don't hold it any more, the other thread does.
+            *x.lock_with_cas() += 1;
+        });
+    }
+
+    #[test]
+    fn clone_is_independent() {
+        let x = SpinLock::new(vec![1, 2]);
+        let y = x.clone();
+        y.lock_with_cas().push(3);
+        assert_eq!(*x.lock_with_cas(), [1, 2]);
+        assert_eq!(y.into_inner(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn force_unlock_after_forgotten_guard() {
+        let x = SpinLock::new(0);
+        *x.lock_with_cas() = 1;
+        std::mem::forget(x.lock_with_cas());
+        assert!(x.try_lock().is_none());
+        unsafe { x.force_unlock() };
+        *x.lock_with_cas() += 1;
+        assert_eq!(x.into_inner(), 2);
+    }
+
+    #[test]
+    fn leak_keeps_lock_held_until_force_unlock() {
+        let x = SpinLock::new(String::from("a"));
+        let s = Guard::leak(x.lock_with_cas());
+        s.push('b');
+        assert!(x.is_locked());
+        unsafe { x.force_unlock() };
+        assert_eq!(*x.lock_with_cas(), "ab");
+    }
+
+    #[test]
+    fn try_lock_weak_retried_succeeds() {
+        let x = SpinLock::new(0);
+        let held = x.lock_with_cas();
+        assert!(x.try_lock_weak().is_err());
+        drop(held);
+        let mut g = loop {
+            if let Ok(g) = x.try_lock_weak() {
+                break g;
+            }
+        };
+        *g += 1;
+        drop(g);
+        assert_eq!(x.into_inner(), 1);
+    }
+
+    #[test]
+    fn borrow_mut_explicit_access() {
+        let x = SpinLock::new(vec![1]);
+        let mut r = x.borrow_mut();
+        r.get_mut().push(2);
+        assert_eq!(r.get().len(), 2);
+        assert!(x.try_lock().is_none());
+        drop(r);
+        assert!(!x.is_locked());
+        assert_eq!(x.into_inner(), [1, 2]);
+    }
+
+    #[test]
+    fn disarm_rearm_round_trip() {
+        let x = SpinLock::new(0);
+        fn pass_up(x: &SpinLock<i32>) -> DisarmedGuard<'_, i32> {
+            let mut g = Guard::disarm(x.lock_with_cas());
+            *g += 1;
+            g
+        }
+        let g = pass_up(&x);
+        assert!(x.is_locked());
+        let mut g = DisarmedGuard::rearm(g);
+        *g += 1;
+        drop(g);
+        assert!(!x.is_locked());
+        assert_eq!(*x.lock_with_cas(), 2);
+    }
+
+    #[test]
+    fn dropped_disarmed_guard_stays_locked() {
+        let x = SpinLock::new(0);
+        drop(Guard::disarm(x.lock_with_cas()));
+        assert!(x.try_lock().is_none());
+        unsafe { x.force_unlock() };
+        assert_eq!(*x.try_lock().unwrap(), 0);
+    }
+
+    #[test]
+    fn on_unlock_runs_after_release() {
+        let x = SpinLock::new(0);
+        let ran = core::cell::Cell::new(false);
+        let mut g = x.lock_with_on_unlock(|| {
+            assert!(!x.is_locked());
+            // Nothing left of the guard, not even in the bookkeeping.
+            drop(x.try_lock().unwrap());
+            ran.set(true);
+        });
+        *g += 1;
+        assert!(x.is_locked());
+        drop(g);
+        assert!(ran.get());
+        assert_eq!(x.into_inner(), 1);
+    }
+
+    #[test]
+    fn guard_unchecked_takes_over_held_lock() {
+        let x = SpinLock::new(1);
+        // Stands in for the lock having been taken on the other side of an FFI call.
+        assert!(x.raw.try_lock());
+        let mut g = unsafe { x.guard_unchecked() };
+        assert!(x.try_lock().is_none());
+        *g += 1;
+        drop(g);
+        assert!(!x.is_locked());
+        assert_eq!(*x.lock_with_cas(), 2);
+    }
+
+    #[test]
+    fn try_unlock_reports_not_locked() {
+        let x = SpinLock::new(0);
+        assert_eq!(unsafe { x.try_unlock() }, Err(UnlockError::NotLocked));
+        std::mem::forget(x.lock_with_cas());
+        assert_eq!(unsafe { x.try_unlock() }, Ok(()));
+        assert!(!x.is_locked());
+    }
+
+    #[test]
+    fn from_array_in_static() {
+        static LOCKS: [SpinLock<u32>; 4] = SpinLock::from_array([0, 10, 20, 30]);
+        thread::scope(|s| {
+            for lock in &LOCKS {
+                s.spawn(move || *lock.lock_with_cas() += 1);
+            }
+        });
+        let values: Vec<u32> = LOCKS.iter().map(|l| *l.lock_with_cas()).collect();
+        assert_eq!(values, [1, 11, 21, 31]);
+    }
+
+    #[test]
+    fn as_mut_ptr_points_at_guarded_value() {
+        let x = SpinLock::new(5);
+        let ptr = x.as_mut_ptr();
+        let mut g = x.lock_with_cas();
+        *g += 1;
+        assert_eq!(unsafe { *ptr }, *g);
+        assert!(core::ptr::eq(ptr, &*g));
+    }
+
+    #[test]
+    fn eq_compares_values() {
+        assert_eq!(SpinLock::new(1), SpinLock::new(1));
+        assert_ne!(SpinLock::new(1), SpinLock::new(2));
+        let x = SpinLock::new(3);
+        assert!(x == x);
+        let nan = SpinLock::new(f64::NAN);
+        assert!(nan != nan);
+    }
+
+    #[test]
+    // Exactly the interior mutability the `Hash` docs warn about; we don't mutate.
+    #[allow(clippy::mutable_key_type)]
+    fn equal_values_hash_alike() {
+        use std::collections::HashSet;
+
+        let mut set = HashSet::new();
+        assert!(set.insert(SpinLock::new(1)));
+        assert!(!set.insert(SpinLock::new(1)));
+        assert!(set.insert(SpinLock::new(2)));
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&SpinLock::new(2)));
+    }
+
+    #[test]
+    fn levels_locked_top_down() {
+        let high = SpinLock::with_level(0, 10);
+        let low = SpinLock::with_level(0, 5);
+        let plain = SpinLock::new(0);
+        for _ in 0..2 {
+            let _h = high.lock_with_cas();
+            let _p = plain.lock_with_cas();
+            let _l = low.lock_with_cas();
+        }
+        // Out-of-order release doesn't confuse it.
+        let h = high.lock_with_cas();
+        let l = low.lock_with_cas();
+        drop(h);
+        drop(l);
+        drop(low.lock_with_cas());
+        drop(high.lock_with_cas());
+    }
+
+    #[cfg(all(debug_assertions, feature = "std"))]
+    #[test]
+    #[should_panic(expected = "lock ordering violation")]
+    fn level_locked_bottom_up_panics() {
+        let high = SpinLock::with_level(0, 10);
+        let low = SpinLock::with_level(0, 5);
+        let _l = low.lock_with_cas();
+        let _h = high.lock_with_cas();
+    }
+
+    #[cfg(all(debug_assertions, feature = "std"))]
+    #[test]
+    #[should_panic(expected = "locking level 7 while holding level 5")]
+    fn level_checked_against_every_held_lock() {
+        let (l5, l10, l7) = (
+            SpinLock::with_level(0, 5),
+            SpinLock::with_level(0, 10),
+            SpinLock::with_level(0, 7),
+        );
+        let _a = l5.try_lock().unwrap();
+        // Not checked, but now 10 is the innermost level held.
+        let _b = l10.try_lock().unwrap();
+        let _c = l7.lock_with_cas();
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn lock_yielding_many_threads() {
+        const THREADS: usize = 16;
+        let x = SpinLock::new([0; THREADS]);
+        thread::scope(|s| {
+            for i in 0..THREADS {
+                let x = &x;
+                s.spawn(move || {
+                    for _ in 0..200 {
+                        x.lock_yielding()[i] += 1;
+                    }
+                });
+            }
+        });
+        // Every thread got all its turns, none was starved.
+        assert_eq!(x.into_inner(), [200; THREADS]);
+    }
+
+    #[test]
+    fn guard_shared_with_scoped_readers() {
+        let x = SpinLock::new(vec![1, 2, 3]);
+        let g = x.lock_with_cas();
+        let shared = &g;
+        let sums: Vec<i32> = thread::scope(|s| {
+            let readers: Vec<_> = (0..4)
+                .map(|_| s.spawn(move || shared.iter().sum()))
+                .collect();
+            readers.into_iter().map(|r| r.join().unwrap()).collect()
+        });
+        assert_eq!(sums, [6; 4]);
+        assert!(x.is_locked());
+        drop(g);
+        assert!(!x.is_locked());
+    }
+
+    #[test]
+    fn shares_send_but_not_sync_value() {
+        let x = SpinLock::new(core::cell::Cell::new(0));
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let g = x.lock_with_cas();
+                    g.set(g.get() + 1);
+                });
+            }
+        });
+        assert_eq!(x.into_inner().get(), 4);
+    }
+
+    #[test]
+    fn option_take_only_once() {
+        let x = SpinLock::new(Some(String::from("conn")));
+        assert!(x.is_some());
+        let got: Vec<Option<String>> = thread::scope(|s| {
+            let a = s.spawn(|| x.take());
+            let b = s.spawn(|| x.take());
+            vec![a.join().unwrap(), b.join().unwrap()]
+        });
+        assert_eq!(got.iter().flatten().collect::<Vec<_>>(), ["conn"]);
+        assert!(!x.is_some());
+
+        assert_eq!(*x.get_or_insert_with(|| String::from("new")), "new");
+        assert_eq!(*x.get_or_insert_with(|| unreachable!()), "new");
+        assert_eq!(x.take().as_deref(), Some("new"));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn vec_shortcuts_from_several_threads() {
+        let x = SpinLock::new(Vec::new());
+        thread::scope(|s| {
+            for t in 0..4 {
+                let x = &x;
+                s.spawn(move || {
+                    for i in 0..100 {
+                        x.push(t * 100 + i);
+                    }
+                });
+            }
+        });
+        assert_eq!(x.len(), 400);
+        let popped: usize = thread::scope(|s| {
+            let handles: Vec<_> = (0..2)
+                .map(|_| s.spawn(|| (0..50).filter(|_| x.pop().is_some()).count()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+        assert_eq!(popped, 100);
+        assert_eq!(x.len(), 300);
+        let mut rest = x.lock_with_cas().clone();
+        rest.sort_unstable();
+        rest.dedup();
+        assert_eq!(rest.len(), 300);
+        x.clear();
+        x.shrink_to_fit();
+        assert_eq!(x.lock_with_cas().capacity(), 0);
+        x.reserve(10);
+        assert!(x.lock_with_cas().capacity() >= 10);
+        assert!(x.is_empty());
+        assert_eq!(x.pop(), None);
+    }
+
+    #[test]
+    fn with_fields_updates_both_at_once() {
+        let queue = SpinLock::new((vec![1, 2, 3], 0usize));
+        thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    queue.with_fields(|items, taken| {
+                        items.pop().unwrap();
+                        *taken += 1;
+                    })
+                });
+            }
+        });
+        let mut guard = queue.lock_with_cas();
+        let (items, taken) = Guard::fields(&mut guard);
+        // Both changed together, three times.
+        assert_eq!((items.len(), *taken), (0, 3));
+        items.push(9);
+        *taken = 0;
+        drop(guard);
+        assert_eq!(queue.into_inner(), (vec![9], 0));
+    }
+
+    #[test]
+    fn split_pair_into_two_locks() {
+        let (nums, text) = SpinLock::new((vec![1], String::new())).split();
+        let nums_guard = nums.lock_with_cas();
+        thread::scope(|s| {
+            // Not blocked by the other half being held.
+            s.spawn(|| text.lock_with_cas().push_str("free"));
+        });
+        drop(nums_guard);
+        thread::scope(|s| {
+            s.spawn(|| nums.lock_with_cas().push(2));
+            s.spawn(|| text.lock_with_cas().push('!'));
+        });
+        assert_eq!(nums.into_inner(), [1, 2]);
+        assert_eq!(text.into_inner(), "free!");
+    }
+
+    #[test]
+    fn replace_returns_old_value() {
+        let x = SpinLock::new(String::from("old"));
+        assert_eq!(x.replace(String::from("new")), "old");
+        assert_eq!(x.into_inner(), "new");
+    }
+
+    #[test]
+    fn replace_and_into_inner_drop_nothing_themselves() {
+        use std::sync::atomic::AtomicUsize;
+
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Counted(u8);
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let copy = SpinLock::new(1u8);
+        assert_eq!(copy.replace(2), 1);
+        assert_eq!(copy.into_inner(), 2);
+
+        let counted = SpinLock::new(Counted(1));
+        let old = counted.replace(Counted(2));
+        assert_eq!(DROPS.load(Ordering::Relaxed), 0);
+        let inner = counted.into_inner();
+        assert_eq!((old.0, inner.0), (1, 2));
+        assert_eq!(DROPS.load(Ordering::Relaxed), 0);
+        drop((old, inner));
+        assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn replace_with_doubles_under_contention() {
+        let x = SpinLock::new(1u64);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..10 {
+                        x.replace_with(|n| n * 2);
+                    }
+                });
+            }
+        });
+        assert_eq!(*x.lock_with_cas(), 1 << 40);
+
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            x.replace_with(|_| panic!("boom"))
+        }));
+        assert!(r.is_err());
+        assert!(!x.is_locked());
+        assert_eq!(x.into_inner(), 0);
+    }
+
+    #[test]
+    fn peek_is_a_snapshot() {
+        let x = SpinLock::new(vec![1, 2]);
+        let mut before = x.peek();
+        assert!(!x.is_locked());
+        x.lock_with_cas().push(3);
+        before.push(10);
+        assert_eq!(before, [1, 2, 10]);
+        assert_eq!(x.peek(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn swap_values() {
+        let a = SpinLock::new(1);
+        let b = SpinLock::new(2);
+        a.swap(&b);
+        b.swap(&a);
+        b.swap(&a);
+        assert_eq!((*a.lock_with_cas(), *b.lock_with_cas()), (2, 1));
+        a.swap(&a);
+        assert_eq!(a.into_inner(), 2);
+    }
+
+    #[test]
+    fn update_from_several_threads() {
+        let x = SpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..250 {
+                        x.update(|n| *n += 1);
+                    }
+                });
+            }
+        });
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            x.update(|_| panic!("boom"))
+        }));
+        assert!(r.is_err());
+        assert!(!x.is_locked());
+        assert_eq!(x.into_inner(), 1000);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn try_lock_fair_some_when_free_none_when_held() {
+        let x = SpinLock::new(0);
+        *x.try_lock_fair().unwrap() += 1;
+        let g = x.lock_with_cas();
+        thread::scope(|s| {
+            s.spawn(|| assert!(x.try_lock_fair().is_none()));
+        });
+        drop(g);
+        assert_eq!(*x.try_lock_fair().unwrap(), 1);
+    }
+
+    #[test]
+    fn poll_lock_until_ready() {
+        use core::task::Poll;
+
+        let x = SpinLock::new(0);
+        let g = x.lock_with_cas();
+        assert!(x.poll_lock().is_pending());
+        thread::scope(|s| {
+            let poller = s.spawn(|| {
+                let mut polls = 0;
+                loop {
+                    polls += 1;
+                    if let Poll::Ready(mut g) = x.poll_lock() {
+                        *g += 1;
+                        return polls;
+                    }
+                    thread::yield_now();
+                }
+            });
+            thread::sleep(std::time::Duration::from_millis(10));
+            drop(g);
+            assert!(poller.join().unwrap() > 1);
+        });
+        assert_eq!(x.into_inner(), 1);
+    }
+
+    #[test]
+    fn try_lock_backoff_gives_up_after_its_attempts() {
+        let x = SpinLock::new(0);
+        let g = x.lock_with_cas();
+        thread::scope(|s| {
+            s.spawn(|| {
+                assert!(x.try_lock_backoff(20).is_none());
+                assert!(x.try_lock_backoff(0).is_none());
+            });
+        });
+        #[cfg(feature = "stats")]
+        assert_eq!(x.stats().spins, 19);
+        drop(g);
+        *x.try_lock_backoff(1).unwrap() += 1;
+        thread::scope(|s| {
+            let g = x.lock_with_cas();
+            let waiter = s.spawn(|| x.try_lock_backoff(u32::MAX).map(|mut g| *g += 1));
+            thread::sleep(std::time::Duration::from_millis(5));
+            drop(g);
+            assert!(waiter.join().unwrap().is_some());
+        });
+        assert_eq!(x.into_inner(), 2);
+    }
+
+    #[test]
+    fn try_lock_spins_gives_up() {
+        let x = SpinLock::new(0);
+        let g = x.lock_with_cas();
+        assert!(x.try_lock_spins(100).is_none());
+        assert!(x.try_lock_spins(0).is_none());
+        drop(g);
+        assert!(x.try_lock_spins(100).is_some());
+        assert!(x.try_lock_spins(0).is_some());
+    }
+
+    #[test]
+    fn guard_knows_its_lock() {
+        let x = SpinLock::new(0);
+        let g = x.lock_with_cas();
+        assert!(core::ptr::eq(Guard::spinlock(&g), &x));
+    }
+
+    // The numbers in the "Size" docs; only a release build without the
+    // bookkeeping features has them.
+    #[cfg(not(any(
+        debug_assertions,
+        feature = "stats",
+        feature = "tracing",
+        feature = "park",
+        feature = "smart",
+        feature = "history"
+    )))]
+    #[test]
+    fn release_size_is_as_documented() {
+        let expected = if cfg!(feature = "std") { 24 } else { 16 };
+        assert_eq!(core::mem::size_of::<SpinLock<u64>>(), expected);
+    }
+
+    #[test]
+    fn integer_ops_from_many_threads() {
+        let total = SpinLock::new(0u64);
+        let signed = SpinLock::new(0i32);
+        thread::scope(|s| {
+            for i in 1..=4u64 {
+                let (total, signed) = (&total, &signed);
+                s.spawn(move || {
+                    for _ in 0..1000 {
+                        total.fetch_add(i);
+                        signed.fetch_sub(1);
+                    }
+                });
+            }
+        });
+        assert_eq!(total.load(), 10_000);
+        assert_eq!(signed.load(), -4000);
+        assert_eq!(total.fetch_sub(10_000), 10_000);
+        total.store(7);
+        assert_eq!(total.fetch_add(1), 7);
+        // Wraps like `AtomicU8` would.
+        let byte = SpinLock::new(u8::MAX);
+        assert_eq!(byte.fetch_add(2), u8::MAX);
+        assert_eq!(byte.load(), 1);
+    }
+
+    #[test]
+    fn reenter_reads_under_the_held_lock() {
+        // Stands in for code that only gets the lock, e.g. a callback.
+        fn total(lock: &SpinLock<Vec<u32>>, token: &LockToken<'_, Vec<u32>>) -> u32 {
+            lock.reenter(token).iter().sum()
+        }
+
+        let lock = SpinLock::new(vec![1, 2]);
+        let mut guard = lock.lock_with_cas();
+        guard.push(3);
+        let token = Guard::token(&guard);
+        assert_eq!(total(&lock, &token), 6);
+        assert!(lock.is_locked());
+        // Reading through the guard itself is still allowed next to the token.
+        assert_eq!(lock.reenter(&token).len(), guard.len());
+        guard.push(4);
+        assert_eq!(total(&lock, &Guard::token(&guard)), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "different SpinLock")]
+    fn reenter_rejects_a_token_of_another_lock() {
+        let (a, b) = (SpinLock::new(0), SpinLock::new(0));
+        let guard = a.lock_with_cas();
+        b.reenter(&Guard::token(&guard));
+    }
+
+    // Run with and without `profiling`, which only changes inlining.
+    #[test]
+    fn lock_try_lock_unlock_under_contention() {
+        let x = SpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for i in 0..1000 {
+                        if i % 2 == 0 {
+                            *x.lock() += 1;
+                        } else if let Some(mut g) = x.try_lock() {
+                            *g += 1;
+                        } else {
+                            drop(Guard::disarm(x.lock()));
+                            unsafe {
+                                *x.as_mut_ptr() += 1;
+                                x.unlock();
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(x.into_inner(), 4000);
+    }
+
+    #[test]
+    fn guard_formats_as_its_lock_address() {
+        let locks = [SpinLock::new(0), SpinLock::new(1)];
+        let g = locks[1].lock_with_cas();
+        assert_eq!(format!("{g:p}"), format!("{:p}", &locks[1]));
+        assert_ne!(format!("{g:p}"), format!("{:p}", &locks[0]));
+        assert_eq!(format!("{g:p}"), format!("{:p}", &locks[1] as *const _));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn locked_vec_shards() {
+        let shards = locked_vec(0..16);
+        assert_eq!(shards.len(), 16);
+        thread::scope(|s| {
+            for i in [1, 5, 5, 9] {
+                let shards = &shards;
+                s.spawn(move || *shards[i].lock_with_cas() += 100);
+            }
+        });
+        let values: Vec<i32> = shards.into_iter().map(SpinLock::into_inner).collect();
+        assert_eq!(values[..10], [0, 101, 2, 3, 4, 205, 6, 7, 8, 109]);
+        assert_eq!(values[15], 15);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tracing_test::traced_test]
+    #[test]
+    fn tracing_events_on_acquire_and_release() {
+        let x = SpinLock::named(0, "counter");
+        *x.lock_with_cas() += 1;
+        assert!(logs_contain("spinlock acquired"));
+        assert!(logs_contain("spinlock released"));
+        assert!(logs_contain("counter"));
+    }
+
+    #[cfg(all(feature = "tracing", feature = "std"))]
+    #[tracing_test::traced_test]
+    #[test]
+    fn contention_threshold_warns_when_exceeded() {
+        let x = SpinLock::with_contention_threshold(0, "hot", 10);
+        *x.lock_with_cas() += 1;
+        assert!(!logs_contain("contended spinlock acquisition"));
+        let g = x.lock_with_cas();
+        thread::scope(|s| {
+            // `logs_contain` only sees events inside the test's span.
+            let span = tracing::Span::current();
+            let x = &x;
+            let waiter = s.spawn(move || {
+                let _span = span.enter();
+                *x.lock_with_cas() += 1;
+            });
+            // Let the waiter spin well past the threshold.
+            thread::sleep(std::time::Duration::from_millis(20));
+            drop(g);
+            waiter.join().unwrap();
+        });
+        assert!(logs_contain("contended spinlock acquisition"));
+        assert!(logs_contain("hot"));
+        assert!(logs_contain("threshold=10"));
+    }
+
+    #[cfg(all(feature = "tracing", feature = "std"))]
+    #[tracing_test::traced_test]
+    #[test]
+    fn giving_up_does_not_count_towards_the_threshold() {
+        let x = SpinLock::with_contention_threshold(0, "hot", 10);
+        let g = x.lock_with_cas();
+        assert!(x.try_lock_spins(100).is_none());
+        assert!(x
+            .try_lock_for(std::time::Duration::from_millis(1))
+            .is_none());
+        drop(g);
+        // Uncontended: only the spins above could make it warn.
+        *x.lock_with_cas() += 1;
+        assert!(!logs_contain("contended spinlock acquisition"));
+    }
+
+    #[test]
+    fn read_shared_derefs_to_shared_ref() {
+        let x = SpinLock::new(vec![1, 2]);
+        let r = x.read_shared();
+        let v: &Vec<i32> = &r;
+        assert_eq!(v, &[1, 2]);
+        assert!(x.try_lock().is_none());
+        drop(r);
+        assert!(!x.is_locked());
+    }
+
+    #[test]
+    fn update_if_checks_predicate() {
+        let x = SpinLock::new(1);
+        assert!(x.update_if(|&n| n == 1, |n| *n = 2));
+        assert!(!x.update_if(|&n| n == 1, |n| *n = 3));
+        assert_eq!(x.into_inner(), 2);
+    }
+
+    #[test]
+    fn lock_reporting_says_whether_it_waited() {
+        let x = SpinLock::new(0);
+        let (g, waited) = x.lock_reporting();
+        assert!(!waited);
+        let waited = thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                let (mut g, waited) = x.lock_reporting();
+                *g += 1;
+                waited
+            });
+            thread::sleep(std::time::Duration::from_millis(20));
+            drop(g);
+            waiter.join().unwrap()
+        });
+        assert!(waited);
+        assert!(!x.lock_reporting().1);
+    }
+
+    #[test]
+    fn lock_counting_reports_spins() {
+        let x = SpinLock::new(0);
+        let (g, spins) = x.lock_counting();
+        assert_eq!(spins, 0);
+        let spins = thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                let (mut g, spins) = x.lock_counting();
+                *g += 1;
+                spins
+            });
+            thread::sleep(std::time::Duration::from_millis(20));
+            drop(g);
+            waiter.join().unwrap()
+        });
+        assert!(spins > 0);
+        assert_eq!(x.into_inner(), 1);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn contention_hint_after_contended_attempts() {
+        let x = SpinLock::new(0);
+        for _ in 0..10 {
+            drop(x.lock_with_cas());
+        }
+        assert!(!x.contention_hint());
+        let g = x.lock_with_cas();
+        assert!(x.contention_hint());
+        assert!(x.try_lock_spins(100).is_none());
+        drop(g);
+        assert!(x.contention_hint());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn with_lock_timeout_runs_or_gives_up() {
+        let x = SpinLock::new(1);
+        assert_eq!(
+            x.with_lock_timeout(Duration::from_millis(10), |n| *n + 1),
+            Some(2)
+        );
+        let g = x.lock_with_cas();
+        thread::scope(|s| {
+            s.spawn(|| {
+                assert_eq!(
+                    x.with_lock_timeout(Duration::from_millis(10), |n| *n + 1),
+                    None
+                );
+            });
+        });
+        drop(g);
+    }
+
+    #[test]
+    fn from_cell_locks_normally() {
+        static LOCK: SpinLock<i32> = SpinLock::from_cell(UnsafeCell::new(4));
+        assert!(!LOCK.is_locked());
+        *LOCK.lock_with_cas() += 1;
+        assert_eq!(*LOCK.lock_with_cas(), 5);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn wait_until_unlocked_returns_after_release() {
+        let x = SpinLock::new(0);
+        let g = x.lock_with_cas();
+        let start = Instant::now();
+        thread::scope(|s| {
+            s.spawn(|| {
+                x.wait_until_unlocked();
+                assert!(start.elapsed() >= Duration::from_millis(20));
+            });
+            thread::sleep(Duration::from_millis(20));
+            drop(g);
+        });
+        x.wait_until_unlocked();
+        assert!(x.try_lock().is_some());
+    }
+
+    #[test]
+    fn spin_budget_in_type_under_contention() {
+        let x: SpinLock<i32, 16> = SpinLock::with_spin_budget(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *x.lock_with_cas() += 1;
+                        *x.lock_with_swap() += 1;
+                    }
+                });
+            }
+        });
+        let y = SpinLock::<i32, 16>::default();
+        let (a, b) = lock!(&x, &y);
+        assert_eq!((*a, *b), (8000, 0));
+    }
+
+    #[test]
+    fn try_with_runs_only_when_free() {
+        let x = SpinLock::new(1);
+        assert_eq!(x.try_with(|n| *n + 1), Some(2));
+        let g = x.lock_with_cas();
+        assert_eq!(x.try_with(|n| *n + 1), None);
+        drop(g);
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            x.try_with(|_| panic!("boom"))
+        }));
+        assert!(r.is_err());
+        assert!(!x.is_locked());
     }
 }
@@ -0,0 +1,61 @@
+use core::ops::DerefMut;
+
+use crate::{Guard, MappedGuard, WriteGuard};
+
+/// Any guard that gives exclusive access to a locked value and lets go of
+/// the lock when dropped, so generic code can take whichever one the caller
+/// has: a `Guard`, a `MappedGuard` narrowed down to the part it needs, or
+/// a `RwSpinLock` `WriteGuard`.
+///
+/// ```
+/// use my_spin_lock::{LockGuard, RwSpinLock, SpinLock};
+///
+/// fn bump(mut guard: impl LockGuard<Target = u32>) {
+///     *guard += 1;
+/// }
+///
+/// let a = SpinLock::new(1);
+/// let b = RwSpinLock::new(1);
+/// bump(a.lock_with_cas());
+/// bump(b.write());
+/// assert_eq!((*a.lock_with_cas(), *b.read()), (2, 2));
+/// ```
+pub trait LockGuard: DerefMut {}
+
+impl<T: ?Sized, const B: usize> LockGuard for Guard<'_, T, B> {}
+
+impl<T: ?Sized, U: ?Sized, const B: usize> LockGuard for MappedGuard<'_, T, U, B> {}
+
+impl<T> LockGuard for WriteGuard<'_, T> {}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use crate::{RwSpinLock, SpinLock};
+
+    fn push_and_count<G: LockGuard<Target = Vec<u8>>>(mut guard: G, byte: u8) -> usize {
+        guard.push(byte);
+        guard.len()
+    }
+
+    #[test]
+    fn generic_code_takes_any_guard() {
+        let lock = SpinLock::new(vec![1]);
+        assert_eq!(push_and_count(lock.lock_with_cas(), 2), 2);
+        assert!(!lock.is_locked());
+
+        let pair = SpinLock::new((0, vec![]));
+        assert_eq!(
+            push_and_count(Guard::map(pair.lock_with_cas(), |p| &mut p.1), 3),
+            1
+        );
+
+        let rw = RwSpinLock::new(vec![4, 5]);
+        assert_eq!(push_and_count(rw.write(), 6), 3);
+        assert!(rw.try_write().is_some());
+
+        assert_eq!(*lock.lock_with_cas(), [1, 2]);
+        assert_eq!(pair.into_inner().1, [3]);
+        assert_eq!(*rw.read(), [4, 5, 6]);
+    }
+}
@@ -0,0 +1,195 @@
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+
+/// A queue lock (Mellor-Crummey & Scott) for heavily contended locks on many
+/// cores.
+///
+/// With `SpinLock` every waiter spins on the same cache line, so each release
+/// invalidates it in all their caches at once. Here each waiter brings its own
+/// `McsNode` and spins on that; the waiters form a linked list and a release
+/// only touches the next node in line. It is also fair (FIFO), like
+/// `TicketSpinLock`.
+///
+/// The price is the node: other threads in the queue write to it through raw
+/// pointers, so it has to stay put until the lock is released. `with_lock`
+/// keeps one on its own stack frame and is always safe:
+///
+/// ```
+/// use my_spin_lock::{McsNode, McsSpinLock};
+///
+/// let lock = McsSpinLock::new(0);
+/// lock.with_lock(|x| *x += 1);
+///
+/// // Or with a node of your own, see `lock` for the rules.
+/// let mut node = McsNode::new();
+/// *unsafe { lock.lock(&mut node) } += 1;
+/// assert_eq!(lock.into_inner(), 2);
+/// ```
+pub struct McsSpinLock<T> {
+    // The last node in the queue, null when unlocked.
+    tail: AtomicPtr<McsNode>,
+    value: UnsafeCell<T>,
+}
+
+/// One thread's place in an `McsSpinLock` queue. Can be reused for the next
+/// `lock()` once the guard is gone.
+#[derive(Debug, Default)]
+pub struct McsNode {
+    // Set by our successor once it has queued up behind us.
+    next: AtomicPtr<McsNode>,
+    // Cleared by our predecessor when it hands the lock over.
+    waiting: AtomicBool,
+}
+
+impl McsNode {
+    pub const fn new() -> Self {
+        Self {
+            next: AtomicPtr::new(ptr::null_mut()),
+            waiting: AtomicBool::new(false),
+        }
+    }
+}
+
+unsafe impl<T> Sync for McsSpinLock<T> where T: Send {}
+
+impl<T> McsSpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            tail: AtomicPtr::new(ptr::null_mut()),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Runs `f` with the lock held, using a node on this stack frame.
+    pub fn with_lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let mut node = McsNode::new();
+        let mut guard = unsafe { self.lock(&mut node) };
+        f(&mut guard)
+    }
+
+    /// Appends `node` to the queue and spins on it until the previous holder
+    /// hands the lock over. The node is borrowed for as long as the guard
+    /// lives and can be reused afterwards.
+    ///
+    /// # Safety
+    ///
+    /// The guard must actually be dropped before `node` goes away. The borrow
+    /// checker stops caring about `node` once the guard is `mem::forget`ed, but
+    /// the queue doesn't: the next thread to lock would write into its memory.
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub unsafe fn lock<'a>(&'a self, node: &'a mut McsNode) -> McsGuard<'a, T> {
+        // From here on other threads access the node too, only through atomics.
+        let node: &'a McsNode = node;
+        node.next.store(ptr::null_mut(), Ordering::Relaxed);
+        node.waiting.store(true, Ordering::Relaxed);
+        let me = node as *const McsNode as *mut McsNode;
+        // `Acquire` pairs with the `Release` of a holder that emptied the queue,
+        // `Release` publishes our node's initialization to the successor.
+        let prev = self.tail.swap(me, Ordering::AcqRel);
+        if !prev.is_null() {
+            // `prev` can't finish its unlock before it has seen this store.
+            unsafe { (*prev).next.store(me, Ordering::Release) };
+            while node.waiting.load(Ordering::Acquire) {
+                core::hint::spin_loop();
+            }
+        }
+        McsGuard {
+            lock: self,
+            node,
+            _not_send: PhantomData,
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+/// Holds an `McsSpinLock`; dropping it hands the lock to the next node.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct McsGuard<'a, T> {
+    lock: &'a McsSpinLock<T>,
+    node: &'a McsNode,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Sync> Sync for McsGuard<'_, T> {}
+
+impl<T> Deref for McsGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for McsGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for McsGuard<'_, T> {
+    fn drop(&mut self) {
+        let me = self.node as *const McsNode as *mut McsNode;
+        let mut next = self.node.next.load(Ordering::Acquire);
+        if next.is_null() {
+            // Nobody behind us: try to empty the queue.
+            if self
+                .lock
+                .tail
+                .compare_exchange(me, ptr::null_mut(), Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+            // Someone swapped themselves into `tail` but hasn't linked up yet.
+            loop {
+                next = self.node.next.load(Ordering::Acquire);
+                if !next.is_null() {
+                    break;
+                }
+                core::hint::spin_loop();
+            }
+        }
+        // After this store the successor owns the lock and may drop its node,
+        // so it is the last thing we do with it.
+        unsafe { (*next).waiting.store(false, Ordering::Release) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn node_can_be_reused() {
+        let x = McsSpinLock::new(0);
+        let mut node = McsNode::new();
+        for _ in 0..3 {
+            *unsafe { x.lock(&mut node) } += 1;
+        }
+        assert_eq!(x.into_inner(), 3);
+    }
+
+    #[test]
+    fn counts_under_contention() {
+        let x = McsSpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        x.with_lock(|x| *x += 1);
+                    }
+                });
+            }
+        });
+        assert_eq!(x.into_inner(), 8000);
+    }
+}
@@ -0,0 +1,252 @@
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    mem::{self, MaybeUninit},
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use crate::{AtomicBool, RawSpinLock};
+
+/// A value that is computed once, on first use, and then read without locking.
+///
+/// Initialization runs under a `RawSpinLock`, so racing threads spin until the
+/// winner is done. Afterwards `get_or_init` is just one `Acquire` load.
+///
+/// ```
+/// use my_spin_lock::SpinOnceCell;
+///
+/// static CONFIG: SpinOnceCell<String> = SpinOnceCell::new();
+///
+/// let a = CONFIG.get_or_init(|| String::from("loaded"));
+/// let b = CONFIG.get_or_init(|| unreachable!());
+/// assert!(std::ptr::eq(a, b));
+/// ```
+pub struct SpinOnceCell<T> {
+    lock: RawSpinLock,
+    // Set with `Release` once `value` is written, never cleared.
+    ready: AtomicBool,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// `&T` is handed to every thread (`Sync`), and the thread that initializes
+/// may not be the one that drops it (`Send`).
+unsafe impl<T> Sync for SpinOnceCell<T> where T: Send + Sync {}
+
+impl<T> SpinOnceCell<T> {
+    const_fn! {
+        pub const fn new() -> Self {
+            Self {
+                lock: RawSpinLock::new(),
+                ready: AtomicBool::new(false),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            }
+        }
+    }
+
+    /// The value, if someone already initialized it.
+    pub fn get(&self) -> Option<&T> {
+        self.ready
+            .load(Ordering::Acquire)
+            .then(|| unsafe { (*self.value.get()).assume_init_ref() })
+    }
+
+    /// Returns the value, running `f` to create it if this is the first call.
+    /// `f` runs at most once; if it panics the cell stays empty and the next
+    /// caller tries again.
+    pub fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+        if let Some(value) = self.get() {
+            return value;
+        }
+        self.lock.lock();
+        // Releases the lock even if `f` panics, or the others would spin forever.
+        struct Unlock<'a>(&'a RawSpinLock);
+        impl Drop for Unlock<'_> {
+            fn drop(&mut self) {
+                unsafe { self.0.unlock() }
+            }
+        }
+        let _unlock = Unlock(&self.lock);
+        // The lock orders us after whoever initialized before us.
+        if !self.ready.load(Ordering::Relaxed) {
+            unsafe { (*self.value.get()).write(f()) };
+            self.ready.store(true, Ordering::Release);
+        }
+        unsafe { (*self.value.get()).assume_init_ref() }
+    }
+}
+
+impl<T> Default for SpinOnceCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for SpinOnceCell<T> {
+    fn drop(&mut self) {
+        // `&mut self`, nobody can be storing to it.
+        if self.ready.load(Ordering::Relaxed) {
+            unsafe { self.value.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SpinOnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("SpinOnceCell").field(value).finish(),
+            None => f.write_str("SpinOnceCell(<uninit>)"),
+        }
+    }
+}
+
+/// Runs a piece of code exactly once, e.g. one-time global setup, with no
+/// value attached (that's `SpinOnceCell`). Needs nothing from `std`.
+///
+/// ```
+/// use my_spin_lock::SpinOnce;
+///
+/// static INIT: SpinOnce = SpinOnce::new();
+///
+/// INIT.call_once(|| println!("only once"));
+/// INIT.call_once(|| unreachable!());
+/// assert!(INIT.is_completed());
+/// ```
+#[derive(Debug, Default)]
+pub struct SpinOnce {
+    state: AtomicU8,
+}
+
+const INCOMPLETE: u8 = 0;
+const RUNNING: u8 = 1;
+const COMPLETE: u8 = 2;
+
+impl SpinOnce {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(INCOMPLETE),
+        }
+    }
+
+    /// Runs `f` if nobody has yet; if another thread is running it right
+    /// now, spins until that is done. Either way, whatever `f` did is visible
+    /// once this returns. If `f` panics it doesn't count, and the next caller
+    /// runs its own `f`.
+    pub fn call_once(&self, f: impl FnOnce()) {
+        if self.is_completed() {
+            return;
+        }
+        loop {
+            match self.state.compare_exchange_weak(
+                INCOMPLETE,
+                RUNNING,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(COMPLETE) => return,
+                // `RUNNING`, or a spurious failure.
+                Err(_) => core::hint::spin_loop(),
+            }
+        }
+        // Back to `INCOMPLETE` if `f` panics, or the others would spin forever.
+        struct Reset<'a>(&'a AtomicU8);
+        impl Drop for Reset<'_> {
+            fn drop(&mut self) {
+                self.0.store(INCOMPLETE, Ordering::Release);
+            }
+        }
+        let reset = Reset(&self.state);
+        f();
+        mem::forget(reset);
+        self.state.store(COMPLETE, Ordering::Release);
+    }
+
+    /// Whether some `call_once` has finished running its closure.
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn racing_threads_see_one_value() {
+        let cell = SpinOnceCell::new();
+        let calls = AtomicUsize::new(0);
+        let seen: Vec<usize> = thread::scope(|s| {
+            let handles: Vec<_> = (0..8)
+                .map(|i| {
+                    let (cell, calls) = (&cell, &calls);
+                    s.spawn(move || {
+                        *cell.get_or_init(|| {
+                            calls.fetch_add(1, Ordering::Relaxed);
+                            i
+                        })
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert!(seen.iter().all(|&v| v == seen[0]));
+        assert_eq!(cell.get(), Some(&seen[0]));
+    }
+
+    #[test]
+    fn panicking_init_leaves_it_empty() {
+        let cell = SpinOnceCell::new();
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cell.get_or_init(|| panic!("boom"));
+        }));
+        assert!(r.is_err());
+        assert_eq!(cell.get(), None);
+        assert_eq!(*cell.get_or_init(|| 5), 5);
+    }
+
+    #[test]
+    fn drops_the_value() {
+        let rc = std::rc::Rc::new(());
+        let cell = SpinOnceCell::new();
+        cell.get_or_init(|| rc.clone());
+        assert_eq!(std::rc::Rc::strong_count(&rc), 2);
+        drop(cell);
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn call_once_runs_once_across_threads() {
+        let once = SpinOnce::new();
+        let calls = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    once.call_once(|| {
+                        calls.fetch_add(1, Ordering::Relaxed);
+                    });
+                    // Nobody gets past `call_once` before the winner is done.
+                    assert_eq!(calls.load(Ordering::Relaxed), 1);
+                });
+            }
+        });
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+        assert!(once.is_completed());
+    }
+
+    #[test]
+    fn panicking_call_once_can_be_retried() {
+        let once = SpinOnce::new();
+        let r = std::panic::catch_unwind(|| once.call_once(|| panic!("boom")));
+        assert!(r.is_err());
+        assert!(!once.is_completed());
+        let mut ran = false;
+        once.call_once(|| ran = true);
+        assert!(ran && once.is_completed());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::{Guard, SpinLock};
+
+/// Locks every `SpinLock` in a tuple of references, always in ascending
+/// address order, and returns the guards in the tuple's own order.
+///
+/// Two threads doing `lock!(a, b)` and `lock!(b, a)` therefore take the locks
+/// in the same global order and can't end up each holding one lock while
+/// spinning on the other. Usually called through the `lock!` macro.
+pub trait LockAll<'a> {
+    type Guards;
+
+    /// Panics if the same lock appears twice, that would spin forever.
+    fn lock_all(self) -> Self::Guards;
+}
+
+macro_rules! impl_lock_all {
+    ($($idx:tt $t:ident $b:ident $lock:ident $guard:ident),+) => {
+        impl<'a, $($t: ?Sized, const $b: usize),+> LockAll<'a> for ($(&'a SpinLock<$t, $b>,)+) {
+            type Guards = ($(Guard<'a, $t, $b>,)+);
+
+            fn lock_all(self) -> Self::Guards {
+                let ($($lock,)+) = self;
+                let addrs = [$($lock as *const SpinLock<$t, $b> as *const () as usize),+];
+                let mut order = [$($idx),+];
+                order.sort_unstable_by_key(|&i| addrs[i]);
+                assert!(
+                    order.windows(2).all(|w| addrs[w[0]] != addrs[w[1]]),
+                    "lock! was given the same SpinLock more than once"
+                );
+                $(let mut $guard = None;)+
+                for i in order {
+                    match i {
+                        $($idx => $guard = Some($lock.lock_with_cas()),)+
+                        _ => unreachable!(),
+                    }
+                }
+                ($($guard.unwrap(),)+)
+            }
+        }
+    };
+}
+
+impl_lock_all!(0 A NA a ga);
+impl_lock_all!(0 A NA a ga, 1 B NB b gb);
+impl_lock_all!(0 A NA a ga, 1 B NB b gb, 2 C NC c gc);
+impl_lock_all!(0 A NA a ga, 1 B NB b gb, 2 C NC c gc, 3 D ND d gd);
+impl_lock_all!(0 A NA a ga, 1 B NB b gb, 2 C NC c gc, 3 D ND d gd, 4 E NE e ge);
+impl_lock_all!(0 A NA a ga, 1 B NB b gb, 2 C NC c gc, 3 D ND d gd, 4 E NE e ge, 5 F NF f gf);
+
+/// Locks several `SpinLock`s at once without risking a lock-order deadlock.
+///
+/// Takes up to six `&SpinLock<_>`s (of possibly different `T`s), acquires them
+/// in address order and returns a tuple of guards in the order they were given.
+///
+/// ```
+/// use my_spin_lock::{lock, SpinLock};
+///
+/// let a = SpinLock::new(1);
+/// let b = SpinLock::new(String::from("x"));
+/// let (mut a, mut b) = lock!(&a, &b);
+/// *a += 1;
+/// b.push('y');
+/// ```
+#[macro_export]
+macro_rules! lock {
+    ($($lock:expr),+ $(,)?) => {
+        $crate::LockAll::lock_all(($(&*$lock,)+))
+    };
+}
+
+/// The two-lock case of `lock!` as a plain function, e.g. for moving
+/// something between two accounts: the locks are taken in address order, so
+/// `lock_two(x, y)` and `lock_two(y, x)` on two threads can't deadlock.
+/// Panics if `a` and `b` are the same lock.
+pub fn lock_two<'a, A: ?Sized, B: ?Sized, const NA: usize, const NB: usize>(
+    a: &'a SpinLock<A, NA>,
+    b: &'a SpinLock<B, NB>,
+) -> (Guard<'a, A, NA>, Guard<'a, B, NB>) {
+    (a, b).lock_all()
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::thread;
+
+    use super::lock_two;
+    use crate::{lock, SpinLock};
+
+    #[test]
+    fn opposite_orders_make_progress() {
+        let a = SpinLock::new(0);
+        let b = SpinLock::new(0);
+        thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    let (mut a, mut b) = lock!(&a, &b);
+                    *a += 1;
+                    *b += 1;
+                }
+            });
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    let (mut b, mut a) = lock!(&b, &a);
+                    *a += 1;
+                    *b += 1;
+                }
+            });
+        });
+        assert_eq!((*a.lock_with_cas(), *b.lock_with_cas()), (2000, 2000));
+    }
+
+    #[test]
+    fn lock_two_transfers_conserve_total() {
+        let x = SpinLock::new(1000);
+        let y = SpinLock::new(1000);
+        thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..2000 {
+                    let (mut from, mut to) = lock_two(&x, &y);
+                    *from -= 1;
+                    *to += 1;
+                }
+            });
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    let (mut from, mut to) = lock_two(&y, &x);
+                    *from -= 1;
+                    *to += 1;
+                    // Never half-done as seen from under both locks.
+                    assert_eq!(*from + *to, 2000);
+                }
+            });
+        });
+        assert_eq!((x.into_inner(), y.into_inner()), (0, 2000));
+    }
+
+    #[test]
+    fn guards_come_back_in_argument_order() {
+        let locks = [SpinLock::new(0), SpinLock::new(1), SpinLock::new(2)];
+        let (c, a, b) = lock!(&locks[2], &locks[0], &locks[1]);
+        assert_eq!((*c, *a, *b), (2, 0, 1));
+    }
+
+    #[test]
+    #[should_panic(expected = "same SpinLock")]
+    fn same_lock_twice_panics() {
+        let a = SpinLock::new(0);
+        let _ = lock!(&a, &a);
+    }
+}
@@ -0,0 +1,291 @@
+use core::{
+    marker::PhantomData,
+    mem::{self, ManuallyDrop},
+    ops::{Deref, DerefMut},
+    ptr,
+};
+use std::{
+    boxed::Box,
+    sync::{Arc, Weak},
+};
+
+use crate::SpinLock;
+
+impl<T: ?Sized, const B: usize> SpinLock<T, B> {
+    /// Like `lock_with_cas`, but the guard keeps its own clone of the `Arc`
+    /// instead of borrowing the lock, so it is `'static` and can be stored or
+    /// moved into another thread. Same idea as `parking_lot`'s `ArcMutexGuard`.
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn lock_arc(self: &Arc<Self>) -> ArcGuard<T, B> {
+        // The owned guard takes over from the borrowed one.
+        mem::forget(self.lock_with_cas());
+        self.disown();
+        ArcGuard {
+            lock: Arc::clone(self),
+            _marker: PhantomData,
+        }
+    }
+
+    /// `lock_arc` for a lock with a single owner: the guard takes the box, so
+    /// it is `'static` and `Send` too. Get the box back with
+    /// `OwnedGuard::into_box`, or just drop the guard to drop both.
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn lock_owned(self: Box<Self>) -> OwnedGuard<T, B> {
+        mem::forget(self.lock_with_cas());
+        self.disown();
+        OwnedGuard {
+            lock: self,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T, const B: usize> SpinLock<T, B> {
+    /// Moves the value onto the heap and hands out the pointer, for data
+    /// structures that store raw pointers. The lock itself (its state, poison
+    /// flag, name and so on) is gone; the pointer owns the value the way
+    /// `Box::into_raw` does, and `from_raw` turns it back into a lock.
+    pub fn into_raw(self) -> *mut T {
+        Box::into_raw(Box::new(self.into_inner()))
+    }
+
+    /// Takes back a value from `into_raw` into a new lock, which starts out
+    /// unlocked and unpoisoned, like one from `with_spin_budget`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `into_raw` (for the same `T`, any `B`) and must
+    /// not be used again afterwards; the new lock owns the value from now on.
+    pub unsafe fn from_raw(ptr: *mut T) -> Self {
+        Self::with_spin_budget(*Box::from_raw(ptr))
+    }
+}
+
+/// `lock_arc` on two locks at once, taken in address order like `lock!`, so
+/// two threads locking the same pair in opposite order can't deadlock. Both
+/// guards are `'static`, e.g. to move them into a worker thread together.
+/// Panics if `a` and `b` are the same lock.
+pub fn lock_pair_arc<A: ?Sized, C: ?Sized, const NA: usize, const NB: usize>(
+    a: &Arc<SpinLock<A, NA>>,
+    b: &Arc<SpinLock<C, NB>>,
+) -> (ArcGuard<A, NA>, ArcGuard<C, NB>) {
+    let addr_a = Arc::as_ptr(a) as *const () as usize;
+    let addr_b = Arc::as_ptr(b) as *const () as usize;
+    assert_ne!(
+        addr_a, addr_b,
+        "lock_pair_arc was given the same SpinLock twice"
+    );
+    if addr_a < addr_b {
+        let ga = a.lock_arc();
+        (ga, b.lock_arc())
+    } else {
+        let gb = b.lock_arc();
+        (a.lock_arc(), gb)
+    }
+}
+
+/// `lock_arc` through a `Weak`, e.g. for a cache that shouldn't keep the
+/// resource alive: `None` if the lock is gone already, otherwise a guard that
+/// keeps it alive for as long as it is held.
+#[must_use = "the lock is released when the guard is dropped"]
+pub fn upgrade_and_lock<T: ?Sized, const B: usize>(
+    weak: &Weak<SpinLock<T, B>>,
+) -> Option<ArcGuard<T, B>> {
+    Some(weak.upgrade()?.lock_arc())
+}
+
+/// Takes the value out of a shared lock once the caller holds the last
+/// `Arc` to it, e.g. after joining the threads that used it. With other
+/// owners left the `Arc` comes back untouched in `Err`.
+///
+/// No locking needed: the last owner can't be racing anybody, and a live
+/// `ArcGuard` holds an `Arc` of its own.
+pub fn try_unwrap<T, const B: usize>(arc: Arc<SpinLock<T, B>>) -> Result<T, Arc<SpinLock<T, B>>> {
+    Arc::try_unwrap(arc).map(SpinLock::into_inner)
+}
+
+/// A guard that owns an `Arc` to its lock, made by `SpinLock::lock_arc`.
+///
+/// Unlike `Guard` it may be sent to another thread (when `T: Send`), the
+/// lock doesn't care which thread releases it. Dropping it releases the lock.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct ArcGuard<T: ?Sized, const B: usize = 0> {
+    lock: Arc<SpinLock<T, B>>,
+    // The `Arc` alone would make us `Sync` for any `T: Send`,
+    // but sharing `&ArcGuard` shares `&T`, so opt out and re-add below.
+    _marker: PhantomData<*const ()>,
+}
+
+unsafe impl<T: ?Sized + Send, const B: usize> Send for ArcGuard<T, B> {}
+unsafe impl<T: ?Sized + Sync, const B: usize> Sync for ArcGuard<T, B> {}
+
+impl<T: ?Sized, const B: usize> Deref for ArcGuard<T, B> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized, const B: usize> DerefMut for ArcGuard<T, B> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized, const B: usize> Drop for ArcGuard<T, B> {
+    fn drop(&mut self) {
+        unsafe { self.lock.drop_guard() };
+    }
+}
+
+/// A guard that owns its boxed lock, made by `SpinLock::lock_owned`.
+/// `Send` and `Sync` under the same conditions as `ArcGuard`.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct OwnedGuard<T: ?Sized, const B: usize = 0> {
+    lock: Box<SpinLock<T, B>>,
+    _marker: PhantomData<*const ()>,
+}
+
+unsafe impl<T: ?Sized + Send, const B: usize> Send for OwnedGuard<T, B> {}
+unsafe impl<T: ?Sized + Sync, const B: usize> Sync for OwnedGuard<T, B> {}
+
+impl<T: ?Sized, const B: usize> OwnedGuard<T, B> {
+    /// Releases the lock and hands the box back.
+    pub fn into_box(guard: Self) -> Box<SpinLock<T, B>> {
+        let guard = ManuallyDrop::new(guard);
+        unsafe {
+            guard.lock.drop_guard();
+            // `guard` is never used or dropped again, so the box moves out once.
+            ptr::read(&guard.lock)
+        }
+    }
+}
+
+impl<T: ?Sized, const B: usize> Deref for OwnedGuard<T, B> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized, const B: usize> DerefMut for OwnedGuard<T, B> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized, const B: usize> Drop for OwnedGuard<T, B> {
+    fn drop(&mut self) {
+        unsafe { self.lock.drop_guard() };
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::{lock_pair_arc, try_unwrap, upgrade_and_lock};
+    use crate::{OwnedGuard, SpinLock};
+
+    #[test]
+    fn arc_guard_moves_into_thread() {
+        let x = Arc::new(SpinLock::new(Vec::new()));
+        let mut g = x.lock_arc();
+        g.push(1);
+        thread::spawn(move || g.push(2)).join().unwrap();
+        assert!(!x.is_locked());
+        assert_eq!(*x.lock_with_cas(), [1, 2]);
+    }
+
+    #[test]
+    fn try_unwrap_only_for_the_last_owner() {
+        let x = Arc::new(SpinLock::new(vec![1]));
+        let other = Arc::clone(&x);
+        let x = try_unwrap(x).unwrap_err();
+        x.lock_with_cas().push(2);
+        drop(other);
+        let g = x.lock_arc();
+        let x = try_unwrap(x).unwrap_err();
+        drop(g);
+        assert_eq!(try_unwrap(x).unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn pair_of_arc_guards_moves_into_thread() {
+        let a = Arc::new(SpinLock::new(1));
+        let b = Arc::new(SpinLock::new(String::from("a")));
+        for i in 0..10 {
+            let (mut ga, mut gb) = if i % 2 == 0 {
+                lock_pair_arc(&a, &b)
+            } else {
+                let (gb, ga) = lock_pair_arc(&b, &a);
+                (ga, gb)
+            };
+            thread::spawn(move || {
+                *ga += 1;
+                gb.push('b');
+            })
+            .join()
+            .unwrap();
+        }
+        assert!(!a.is_locked() && !b.is_locked());
+        assert_eq!(*a.lock_with_cas(), 11);
+        assert_eq!(b.lock_with_cas().len(), 11);
+    }
+
+    #[test]
+    #[should_panic(expected = "same SpinLock twice")]
+    fn pair_of_the_same_arc_panics() {
+        let a = Arc::new(SpinLock::new(0));
+        let _ = lock_pair_arc(&a, &a);
+    }
+
+    #[test]
+    fn upgrade_and_lock_only_while_alive() {
+        let x = Arc::new(SpinLock::new(0));
+        let weak = Arc::downgrade(&x);
+        let mut g = upgrade_and_lock(&weak).unwrap();
+        *g += 1;
+        assert!(x.is_locked());
+        drop(x);
+        // The guard's own `Arc` keeps the lock alive.
+        assert_eq!(*g, 1);
+        drop(g);
+        assert!(upgrade_and_lock(&weak).is_none());
+    }
+
+    #[test]
+    fn raw_pointer_round_trip() {
+        let x = SpinLock::new(String::from("a"));
+        x.lock_with_cas().push('b');
+        let ptr = x.into_raw();
+        unsafe { (*ptr).push('c') };
+        let x = unsafe { SpinLock::<String>::from_raw(ptr) };
+        assert!(!x.is_locked() && !x.is_poisoned());
+        x.lock_with_cas().push('d');
+        assert_eq!(x.into_inner(), "abcd");
+    }
+
+    #[test]
+    fn arc_guard_holds_lock() {
+        let x = Arc::new(SpinLock::new(0));
+        let g = x.lock_arc();
+        assert!(x.try_lock().is_none());
+        drop(g);
+        assert!(x.try_lock().is_some());
+    }
+
+    #[test]
+    fn owned_guard_moves_into_thread_and_gives_box_back() {
+        let mut g = Box::new(SpinLock::new(String::from("a"))).lock_owned();
+        g.push('b');
+        let lock = thread::spawn(move || {
+            assert_eq!(*g, "ab");
+            OwnedGuard::into_box(g)
+        })
+        .join()
+        .unwrap();
+        assert!(!lock.is_locked());
+        assert_eq!(lock.into_inner(), "ab");
+    }
+}
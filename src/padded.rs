@@ -0,0 +1,70 @@
+use core::ops::Deref;
+
+use crate::SpinLock;
+
+/// A `SpinLock` on its own cache line(s), to avoid false sharing.
+///
+/// Locks that sit next to each other, e.g. in an array of shards, can share a
+/// cache line, so hammering one slows down all its neighbours even though they
+/// are independent. Aligning each lock to 128 bytes (two 64 byte lines, since
+/// x86 prefetches lines in pairs, the same choice as crossbeam's
+/// `CachePadded`) keeps them apart, at the price of memory.
+///
+/// Derefs to the `SpinLock`, so all the lock methods are available.
+#[repr(align(128))]
+#[derive(Debug, Default)]
+pub struct PaddedSpinLock<T>(SpinLock<T>);
+
+impl<T> PaddedSpinLock<T> {
+    const_fn! {
+        pub const fn new(value: T) -> Self {
+            Self(SpinLock::new(value))
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0.into_inner()
+    }
+}
+
+impl<T> Deref for PaddedSpinLock<T> {
+    type Target = SpinLock<T>;
+    fn deref(&self) -> &SpinLock<T> {
+        &self.0
+    }
+}
+
+impl<T> From<T> for PaddedSpinLock<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn neighbours_on_separate_lines() {
+        let locks: [PaddedSpinLock<u64>; 4] = Default::default();
+        assert_eq!(core::mem::align_of::<PaddedSpinLock<u64>>(), 128);
+        let a = &locks[0] as *const _ as usize;
+        let b = &locks[1] as *const _ as usize;
+        assert!(b - a >= 128);
+
+        thread::scope(|s| {
+            for lock in &locks {
+                s.spawn(move || {
+                    for _ in 0..10_000 {
+                        *lock.lock_with_cas() += 1;
+                    }
+                });
+            }
+        });
+        for lock in locks {
+            assert_eq!(lock.into_inner(), 10_000);
+        }
+    }
+}
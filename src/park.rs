@@ -0,0 +1,274 @@
+use core::{
+    hint::spin_loop,
+    ptr,
+    sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicU8, Ordering},
+};
+use std::{
+    sync::Arc,
+    thread::{self, Thread},
+};
+
+#[cfg(feature = "park")]
+use crate::{Guard, SpinLock};
+
+/// How many times `lock_parking` tries the lock before it goes to sleep.
+#[cfg(feature = "park")]
+const PARK_SPINS: usize = 100;
+
+const WAITING: u8 = 0;
+const NOTIFIED: u8 = 1;
+// The waiter got the lock by itself, whoever pops this node skips it.
+const ABANDONED: u8 = 2;
+
+struct Waiter {
+    thread: Thread,
+    state: AtomicU8,
+    next: AtomicPtr<Waiter>,
+}
+
+/// The threads parked in `lock_parking` (or in `FutexSpinLock::lock` where
+/// there is no futex), as a lock-free stack of `Arc<Waiter>`s (each pushed
+/// node owns one strong count).
+///
+/// Anyone may push, but popping is serialized by `popping`, which is what
+/// keeps the pop safe from ABA: a node can only leave the stack, and so be
+/// freed, through the single popper.
+pub(crate) struct WaitList {
+    head: AtomicPtr<Waiter>,
+    popping: AtomicBool,
+}
+
+impl WaitList {
+    pub(crate) const fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+            popping: AtomicBool::new(false),
+        }
+    }
+
+    fn push(&self, waiter: &Arc<Waiter>) {
+        let node = Arc::into_raw(Arc::clone(waiter)) as *mut Waiter;
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            waiter.next.store(head, Ordering::Relaxed);
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    /// Parks the calling thread until `ready()` returns `true`, which it
+    /// must only do once a release can no longer be missed (typically: it
+    /// took the lock). Every release has to call `wake_one`.
+    pub(crate) fn wait_until(&self, mut ready: impl FnMut() -> bool) {
+        loop {
+            let waiter = Arc::new(Waiter {
+                thread: thread::current(),
+                state: AtomicU8::new(WAITING),
+                next: AtomicPtr::new(ptr::null_mut()),
+            });
+            self.push(&waiter);
+            fence(Ordering::SeqCst);
+            loop {
+                if ready() {
+                    // If we were notified meanwhile, it's our release that
+                    // wakes the next one instead.
+                    waiter.state.store(ABANDONED, Ordering::Relaxed);
+                    return;
+                }
+                // `park` may return spuriously, only a pop means we're off the stack.
+                if waiter.state.load(Ordering::Acquire) == NOTIFIED {
+                    break;
+                }
+                thread::park();
+            }
+        }
+    }
+
+    /// Called right after the lock was released: wakes the most recent
+    /// waiter that is still asleep, if there is one.
+    pub(crate) fn wake_one(&self) {
+        // Pairs with the fence in `wait_until`: either we see its node, or
+        // its `ready()` after pushing sees the lock free. Without it the
+        // waiter could park right after we looked at an empty stack.
+        fence(Ordering::SeqCst);
+        if self.head.load(Ordering::Relaxed).is_null() {
+            return;
+        }
+        while self.popping.swap(true, Ordering::Acquire) {
+            spin_loop();
+        }
+        let mut woken = None;
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            if head.is_null() {
+                break;
+            }
+            // Still on the stack, so still alive: only we take nodes off.
+            let next = unsafe { (*head).next.load(Ordering::Relaxed) };
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                continue;
+            }
+            let waiter = unsafe { Arc::from_raw(head) };
+            if waiter
+                .state
+                .compare_exchange(WAITING, NOTIFIED, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                woken = Some(waiter);
+                break;
+            }
+        }
+        self.popping.store(false, Ordering::Release);
+        if let Some(waiter) = woken {
+            waiter.thread.unpark();
+        }
+    }
+}
+
+impl Drop for WaitList {
+    fn drop(&mut self) {
+        // Nobody can be waiting on a lock that is being dropped, these are
+        // nodes of waiters that got the lock without being popped.
+        let mut node = *self.head.get_mut();
+        while !node.is_null() {
+            let waiter = unsafe { Arc::from_raw(node) };
+            node = waiter.next.load(Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(feature = "park")]
+impl<T: ?Sized, const B: usize> SpinLock<T, B> {
+    /// A hybrid of spinning and sleeping for locks that may be held for a
+    /// long time: tries a short while, then parks the thread until a
+    /// release wakes it up. Every release checks for parked threads (that's
+    /// what the `park` feature costs when it's on), so the lock may be held
+    /// through any of the other `lock_*` methods in the meantime.
+    ///
+    /// Released locks wake one parked thread, which then competes for the
+    /// lock again; it is not handed over, so there is no fairness.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_parking(&self) -> Guard<'_, T, B> {
+        self.check_deadlock();
+        for _ in 0..PARK_SPINS {
+            if self.raw.try_lock() {
+                return Guard::new(self);
+            }
+            self.record_spin();
+            spin_loop();
+        }
+        self.waiters.wait_until(|| self.raw.try_lock());
+        Guard::new(self)
+    }
+}
+
+#[cfg(all(test, feature = "park"))]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn long_holds_park_instead_of_spinning() {
+        let x = SpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..5 {
+                        let mut g = x.lock_parking();
+                        thread::sleep(Duration::from_millis(5));
+                        *g += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(x.into_inner(), 20);
+    }
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn parked_threads_stop_spinning() {
+        let x = SpinLock::new(());
+        let g = x.lock_with_cas();
+        thread::scope(|s| {
+            s.spawn(|| drop(x.lock_parking()));
+            // Long enough for a spinning waiter to go round millions of times.
+            thread::sleep(Duration::from_millis(50));
+            drop(g);
+        });
+        // `PARK_SPINS`, plus the odd spurious failure of `lock_with_cas`'s weak CAS.
+        assert!(x.stats().spins < 2 * PARK_SPINS, "{:?}", x.stats());
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn parked_lock_reports_the_caller() {
+        let x = SpinLock::new(());
+        let g = x.lock_with_cas();
+        let line = thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                let (g, line) = (x.lock_parking(), line!());
+                drop(g);
+                line
+            });
+            thread::sleep(Duration::from_millis(20));
+            drop(g);
+            waiter.join().unwrap()
+        });
+        let at = x.last_locked_at().unwrap();
+        assert_eq!((at.file(), at.line()), (file!(), line));
+    }
+
+    #[test]
+    fn no_lost_wakeups() {
+        // Releases through plain guards have to wake the parked threads too.
+        let x = SpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    for _ in 0..500 {
+                        *x.lock_parking() += 1;
+                    }
+                });
+            }
+            s.spawn(|| {
+                for _ in 0..500 {
+                    *x.lock_with_cas() += 1;
+                }
+            });
+        });
+        assert_eq!(x.into_inner(), 1500);
+    }
+
+    #[test]
+    fn leftover_waiters_are_freed() {
+        let waiter = || {
+            Arc::new(Waiter {
+                thread: thread::current(),
+                state: AtomicU8::new(ABANDONED),
+                next: AtomicPtr::new(ptr::null_mut()),
+            })
+        };
+        let (a, b, c) = (waiter(), waiter(), waiter());
+        let list = WaitList::new();
+        list.push(&a);
+        list.push(&b);
+        assert_eq!(Arc::strong_count(&a), 2);
+        // Abandoned nodes get skipped and freed, nobody is unparked.
+        list.wake_one();
+        assert_eq!((Arc::strong_count(&a), Arc::strong_count(&b)), (1, 1));
+        list.push(&c);
+        drop(list);
+        assert_eq!(Arc::strong_count(&c), 1);
+    }
+}
@@ -0,0 +1,184 @@
+use core::sync::atomic::Ordering;
+
+use crate::{spin_loop, AtomicBool};
+
+/// Just the locking part of `SpinLock`, without any data attached.
+///
+/// Useful for building other primitives, or for protecting data that lives
+/// somewhere else. Nothing ties the lock to that data, so it's up to the
+/// caller to only touch the data between `lock()` and `unlock()`.
+/// `SpinLock<T>` is a `RawSpinLock` next to an `UnsafeCell<T>`.
+///
+/// # Layout
+///
+/// `repr(transparent)` over its `AtomicBool`, so it is one byte, aligned
+/// to one byte (see `SIZE` / `ALIGN`): `0` means unlocked, `1` locked. That
+/// is a stable promise, C code may embed one in a shared struct and lock it
+/// itself, as long as it speaks the same protocol: an acquire
+/// compare-and-swap from `0` to `1` to lock, a release store of `0` to unlock
+/// (`atomic_compare_exchange_strong_explicit` / `atomic_store_explicit` on an
+/// `_Atomic unsigned char`).
+#[repr(transparent)]
+pub struct RawSpinLock {
+    pub(crate) locked: AtomicBool,
+}
+
+/// The ordering of every store that releases a lock. It has to be (at
+/// least) `Release`: that is what makes the holder's writes visible to the
+/// next thread whose `Acquire` takes the lock. One constant, so the unlock
+/// paths can't drift apart.
+pub(crate) const UNLOCK: Ordering = Ordering::Release;
+
+// `AtomicBool` only needs byte-sized loads and stores to exist, but locking
+// takes a byte-sized compare-and-swap. Targets without one (e.g. ARMv6-M)
+// fail here, instead of on the first `compare_exchange` call.
+const _: () = assert!(
+    is_lock_free(),
+    "my_spin_lock needs native 8-bit compare-and-swap on this target"
+);
+
+/// Whether the lock's `AtomicBool` is a real hardware atomic on this target,
+/// rather than one emulated with a lock underneath. Rust never emulates
+/// atomics, it leaves them out instead, so this is decided at compile time
+/// and anything this crate builds for returns `true`. Handy for an embedded
+/// port to assert on at startup.
+pub const fn is_lock_free() -> bool {
+    cfg!(target_has_atomic = "8")
+}
+
+impl RawSpinLock {
+    /// `size_of::<RawSpinLock>()`, 1 (see Layout above).
+    pub const SIZE: usize = core::mem::size_of::<Self>();
+    /// `align_of::<RawSpinLock>()`, 1 (see Layout above).
+    pub const ALIGN: usize = core::mem::align_of::<Self>();
+
+    const_fn! {
+        pub const fn new() -> Self {
+            Self {
+                locked: AtomicBool::new(false),
+            }
+        }
+    }
+
+    /// Spins with a CAS loop until the lock is ours.
+    pub fn lock(&self) {
+        self.lock_and(|| {});
+    }
+
+    /// `lock()`, calling `on_spin` every time round the loop.
+    pub(crate) fn lock_and(&self, mut on_spin: impl FnMut()) {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            spin_loop();
+            on_spin();
+        }
+    }
+
+    /// Single attempt, `true` if we got the lock.
+    pub fn try_lock(&self) -> bool {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// Advisory only, see `SpinLock::is_locked`.
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
+    /// # Safety
+    ///
+    /// The caller must hold the lock (through `lock()` or a successful
+    /// `try_lock()`) and must be done with the data it protects.
+    pub unsafe fn unlock(&self) {
+        self.locked.store(false, UNLOCK);
+    }
+}
+
+impl Default for RawSpinLock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::{cell::UnsafeCell, thread};
+
+    use super::*;
+
+    #[test]
+    fn lock_try_lock_unlock() {
+        let raw = RawSpinLock::new();
+        assert!(raw.try_lock());
+        assert!(raw.is_locked());
+        assert!(!raw.try_lock());
+        unsafe { raw.unlock() };
+        raw.lock();
+        assert!(!raw.try_lock());
+        unsafe { raw.unlock() };
+        assert!(!raw.is_locked());
+    }
+
+    #[test]
+    fn layout_is_one_atomic_byte() {
+        use std::mem::{align_of, size_of};
+        use std::sync::atomic::AtomicBool;
+
+        assert_eq!(RawSpinLock::SIZE, size_of::<AtomicBool>());
+        assert_eq!(RawSpinLock::ALIGN, align_of::<AtomicBool>());
+        assert_eq!((RawSpinLock::SIZE, RawSpinLock::ALIGN), (1, 1));
+
+        // What C code on the other side would see.
+        let raw = RawSpinLock::new();
+        let byte = &raw as *const RawSpinLock as *const u8;
+        assert_eq!(unsafe { *byte }, 0);
+        raw.lock();
+        assert_eq!(unsafe { *byte }, 1);
+        unsafe { raw.unlock() };
+        assert_eq!(unsafe { *byte }, 0);
+    }
+
+    #[test]
+    fn atomics_are_lock_free() {
+        assert!(is_lock_free());
+        const { assert!(is_lock_free()) };
+    }
+
+    #[test]
+    fn protects_external_data() {
+        struct Counter {
+            lock: RawSpinLock,
+            count: UnsafeCell<u32>,
+        }
+        unsafe impl Sync for Counter {}
+
+        impl Counter {
+            fn incr(&self) {
+                self.lock.lock();
+                unsafe {
+                    *self.count.get() += 1;
+                    self.lock.unlock();
+                }
+            }
+        }
+
+        let c = Counter {
+            lock: RawSpinLock::new(),
+            count: UnsafeCell::new(0),
+        };
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        c.incr();
+                    }
+                });
+            }
+        });
+        assert_eq!(c.count.into_inner(), 4000);
+    }
+}
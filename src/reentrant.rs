@@ -0,0 +1,134 @@
+use core::{
+    cell::{Cell, UnsafeCell},
+    marker::PhantomData,
+    ops::Deref,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::current_thread_id;
+
+/// A spin lock the owning thread may lock again without deadlocking.
+///
+/// It remembers which thread holds it and how many times; a nested `lock()`
+/// from the owner just bumps the count, and the lock is only released when
+/// the outermost guard drops. Since several guards of one thread can be alive
+/// at the same time, they only give out `&T`. Put a `Cell`/`RefCell` inside
+/// if you need to mutate.
+pub struct ReentrantSpinLock<T> {
+    // `current_thread_id()` of the holder, 0 when unlocked.
+    owner: AtomicUsize,
+    // Only ever touched by the owner.
+    count: Cell<usize>,
+    value: UnsafeCell<T>,
+}
+
+/// Only one thread at a time gets to see the value, so `T: Send` is enough.
+unsafe impl<T> Sync for ReentrantSpinLock<T> where T: Send {}
+
+impl<T> ReentrantSpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            owner: AtomicUsize::new(0),
+            count: Cell::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Returns right away if this thread already holds the lock,
+    /// otherwise spins until it is free.
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn lock(&self) -> ReentrantGuard<'_, T> {
+        let me = current_thread_id();
+        // Only we could have stored our own id, so `Relaxed` is enough to
+        // recognise ourselves; anyone else's id just sends us to the CAS.
+        if self.owner.load(Ordering::Relaxed) != me {
+            while self
+                .owner
+                .compare_exchange_weak(0, me, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                core::hint::spin_loop();
+            }
+        }
+        let count = self.count.get();
+        self.count.set(
+            count
+                .checked_add(1)
+                .expect("ReentrantSpinLock count overflow"),
+        );
+        ReentrantGuard {
+            lock: self,
+            _not_send: PhantomData,
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+/// Shared access to the value; the lock is released when the last guard of
+/// the owning thread is dropped. `!Send`, the count belongs to this thread.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct ReentrantGuard<'a, T> {
+    lock: &'a ReentrantSpinLock<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<T> Deref for ReentrantGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for ReentrantGuard<'_, T> {
+    fn drop(&mut self) {
+        let count = self.lock.count.get() - 1;
+        self.lock.count.set(count);
+        if count == 0 {
+            self.lock.owner.store(0, Ordering::Release);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, thread};
+
+    use super::*;
+
+    #[test]
+    fn relock_on_same_thread() {
+        let x = ReentrantSpinLock::new(RefCell::new(0));
+        let outer = x.lock();
+        let inner = x.lock();
+        *inner.borrow_mut() += 1;
+        drop(inner);
+        assert_eq!(*outer.borrow(), 1);
+        thread::scope(|s| {
+            s.spawn(|| assert_ne!(x.owner.load(Ordering::Relaxed), 0));
+        });
+        drop(outer);
+        assert_eq!(x.owner.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn excludes_other_threads() {
+        let x = ReentrantSpinLock::new(RefCell::new(0));
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..500 {
+                        let a = x.lock();
+                        let b = x.lock();
+                        *b.borrow_mut() += 1;
+                        drop(b);
+                        *a.borrow_mut() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(x.into_inner().into_inner(), 4000);
+    }
+}
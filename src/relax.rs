@@ -0,0 +1,44 @@
+/// What a lock does between two failed attempts, for `SpinLock::lock_relax`.
+///
+/// The relaxer is passed by `&mut`, so it can keep state across the attempts
+/// of one acquisition (or of many, it is never reset for you), e.g. to count
+/// them or to back off. `Backoff` implements it too, by snoozing.
+pub trait Relax {
+    fn relax(&mut self);
+}
+
+/// Busy waits with `spin_loop()`, like `lock_with_cas`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SpinRelax;
+
+impl Relax for SpinRelax {
+    fn relax(&mut self) {
+        crate::spin_loop();
+    }
+}
+
+/// Gives the time slice away with `yield_now()`, like `lock_yielding`.
+#[cfg(feature = "std")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct YieldRelax;
+
+#[cfg(feature = "std")]
+impl Relax for YieldRelax {
+    fn relax(&mut self) {
+        std::thread::yield_now();
+    }
+}
+
+/// Retries straight away, without even a spin hint.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopRelax;
+
+impl Relax for NoopRelax {
+    fn relax(&mut self) {}
+}
+
+impl Relax for crate::Backoff {
+    fn relax(&mut self) {
+        self.snooze();
+    }
+}
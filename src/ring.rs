@@ -0,0 +1,167 @@
+use crate::SpinLock;
+
+/// A bounded FIFO queue for any number of producers and consumers, holding
+/// at most `N` items in place (no allocation, so it works without `std`).
+///
+/// Like `SpinStack`, a template more than a fast queue: the whole state sits
+/// behind one `SpinLock`, and every operation is one short critical section
+/// that never hands out a guard. Neither side waits: `try_push` gives the
+/// value back when the buffer is full, `try_pop` returns `None` when it's
+/// empty, and callers pick their own retry strategy.
+///
+/// ```
+/// use my_spin_lock::SpinRingBuffer;
+///
+/// let ring: SpinRingBuffer<u32, 2> = SpinRingBuffer::new();
+/// ring.try_push(1).unwrap();
+/// ring.try_push(2).unwrap();
+/// assert_eq!(ring.try_push(3), Err(3));
+/// assert_eq!(ring.try_pop(), Some(1));
+/// ```
+pub struct SpinRingBuffer<T, const N: usize> {
+    state: SpinLock<Ring<T, N>>,
+}
+
+struct Ring<T, const N: usize> {
+    slots: [Option<T>; N],
+    // The oldest item; the next free slot is `len` places after it.
+    head: usize,
+    len: usize,
+}
+
+impl<T, const N: usize> SpinRingBuffer<T, N> {
+    const_fn! {
+        pub const fn new() -> Self {
+            Self {
+                state: SpinLock::new(Ring {
+                    slots: [const { None }; N],
+                    head: 0,
+                    len: 0,
+                }),
+            }
+        }
+    }
+
+    /// Appends `value`, or hands it back if the buffer is full.
+    pub fn try_push(&self, value: T) -> Result<(), T> {
+        let mut ring = self.state.lock_with_cas();
+        if ring.len == N {
+            return Err(value);
+        }
+        let tail = (ring.head + ring.len) % N;
+        ring.slots[tail] = Some(value);
+        ring.len += 1;
+        Ok(())
+    }
+
+    /// Takes the oldest item, if there is one.
+    pub fn try_pop(&self) -> Option<T> {
+        let mut ring = self.state.lock_with_cas();
+        if ring.len == 0 {
+            return None;
+        }
+        let head = ring.head;
+        ring.head = (head + 1) % N;
+        ring.len -= 1;
+        ring.slots[head].take()
+    }
+
+    /// A snapshot, other threads may push or pop right after.
+    pub fn len(&self) -> usize {
+        self.state.lock_with_cas().len
+    }
+
+    /// A snapshot like `len`.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<T, const N: usize> Default for SpinRingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn fifo_and_bounded() {
+        let ring: SpinRingBuffer<i32, 3> = SpinRingBuffer::new();
+        assert_eq!(ring.try_pop(), None);
+        // Goes round the end of the array a few times.
+        for round in 0..5 {
+            for i in 0..3 {
+                ring.try_push(round * 10 + i).unwrap();
+            }
+            assert_eq!(ring.try_push(-1), Err(-1));
+            assert_eq!(ring.len(), ring.capacity());
+            assert_eq!(ring.try_pop(), Some(round * 10));
+            ring.try_push(round * 10 + 3).unwrap();
+            for i in 1..4 {
+                assert_eq!(ring.try_pop(), Some(round * 10 + i));
+            }
+            assert!(ring.is_empty());
+        }
+        let none: SpinRingBuffer<i32, 0> = SpinRingBuffer::new();
+        assert_eq!(none.try_push(1), Err(1));
+        assert_eq!(none.try_pop(), None);
+    }
+
+    #[test]
+    fn producers_and_consumers_lose_nothing() {
+        const PRODUCERS: usize = 4;
+        const ITEMS: usize = 500;
+        let ring: SpinRingBuffer<usize, 16> = SpinRingBuffer::new();
+        let popped = AtomicUsize::new(0);
+        let mut seen: Vec<usize> = thread::scope(|s| {
+            for p in 0..PRODUCERS {
+                let ring = &ring;
+                s.spawn(move || {
+                    for i in 0..ITEMS {
+                        let mut item = p * ITEMS + i;
+                        while let Err(back) = ring.try_push(item) {
+                            item = back;
+                            thread::yield_now();
+                        }
+                    }
+                });
+            }
+            let consumers: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut mine = Vec::new();
+                        while popped.load(Ordering::Relaxed) < PRODUCERS * ITEMS {
+                            match ring.try_pop() {
+                                Some(item) => {
+                                    popped.fetch_add(1, Ordering::Relaxed);
+                                    mine.push(item);
+                                }
+                                None => thread::yield_now(),
+                            }
+                        }
+                        mine
+                    })
+                })
+                .collect();
+            consumers
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        });
+        seen.sort_unstable();
+        assert!(seen.iter().copied().eq(0..PRODUCERS * ITEMS));
+        assert!(ring.is_empty());
+    }
+}
@@ -0,0 +1,329 @@
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A reader-writer spin lock: any number of readers, or a single writer.
+///
+/// The whole state lives in one `AtomicUsize`:
+/// `0` means free, `WRITER` means write locked, otherwise the low bits count
+/// the active readers and the top bit (`UPGRADABLE`) says whether one of them
+/// is the upgradable reader.
+pub struct RwSpinLock<T> {
+    state: AtomicUsize,
+    value: UnsafeCell<T>,
+}
+
+const WRITER: usize = usize::MAX;
+const UPGRADABLE: usize = 1 << (usize::BITS - 1);
+// One less than the reader bits can hold, so `UPGRADABLE | readers` can
+// never look like `WRITER`.
+const MAX_READERS: usize = UPGRADABLE - 2;
+
+/// Readers on different threads share `&T` at the same time, so unlike
+/// `SpinLock` we also need `T: Sync` here (same bounds as `std::sync::RwLock`).
+unsafe impl<T> Sync for RwSpinLock<T> where T: Send + Sync {}
+
+impl<T> RwSpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Spins until no writer holds the lock, then registers as a reader.
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn read(&self) -> ReadGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_read() {
+                return guard;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Spins until there are no readers and no writer.
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn write(&self) -> WriteGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_write() {
+                return guard;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Single attempt to become a reader, fails if a writer holds the lock.
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn try_read(&self) -> Option<ReadGuard<'_, T>> {
+        // Retries while other readers move the count under us (or the CAS
+        // fails spuriously); only a writer or a full count makes it give up.
+        self.state
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |state| {
+                // Also covers `WRITER`, whose reader bits are all set.
+                (state & !UPGRADABLE < MAX_READERS).then(|| state + 1)
+            })
+            .ok()
+            .map(|_| ReadGuard {
+                lock: self,
+                _not_send: PhantomData,
+            })
+    }
+
+    /// Spins until there is no writer and no other upgradable reader.
+    /// Plain readers may come and go meanwhile; see `UpgradableReadGuard`.
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn upgradable_read(&self) -> UpgradableReadGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_upgradable_read() {
+                return guard;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Single attempt at `upgradable_read`.
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn try_upgradable_read(&self) -> Option<UpgradableReadGuard<'_, T>> {
+        // Retries while plain readers come and go; only another upgradable
+        // reader or a writer (whose bits include `UPGRADABLE`) stops it.
+        self.state
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |state| {
+                (state & UPGRADABLE == 0).then_some(state | UPGRADABLE)
+            })
+            .ok()
+            .map(|_| UpgradableReadGuard {
+                lock: self,
+                _not_send: PhantomData,
+            })
+    }
+
+    /// Single attempt to become the writer, fails if anyone holds the lock.
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn try_write(&self) -> Option<WriteGuard<'_, T>> {
+        self.state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| WriteGuard {
+                lock: self,
+                _not_send: PhantomData,
+            })
+    }
+}
+
+/// Shared access, released when dropped.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct ReadGuard<'a, T> {
+    lock: &'a RwSpinLock<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Sync> Sync for ReadGuard<'_, T> {}
+
+impl<T> Deref for ReadGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for ReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(1, Ordering::Release);
+    }
+}
+
+/// Shared access that can later become exclusive without letting another
+/// writer in between: there is at most one upgradable reader, and writers
+/// can't get in while it exists. Plain readers still can.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct UpgradableReadGuard<'a, T> {
+    lock: &'a RwSpinLock<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Sync> Sync for UpgradableReadGuard<'_, T> {}
+
+impl<'a, T> UpgradableReadGuard<'a, T> {
+    /// Spins until the plain readers are gone, then turns into the writer.
+    /// Whatever was read through this guard is still up to date.
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn upgrade(self) -> WriteGuard<'a, T> {
+        let lock = self.lock;
+        while lock
+            .state
+            .compare_exchange_weak(UPGRADABLE, WRITER, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        // The write guard takes over, our `UPGRADABLE` bit is already gone.
+        core::mem::forget(self);
+        WriteGuard {
+            lock,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl<T> Deref for UpgradableReadGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for UpgradableReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.fetch_sub(UPGRADABLE, Ordering::Release);
+    }
+}
+
+/// Exclusive access, released when dropped.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct WriteGuard<'a, T> {
+    lock: &'a RwSpinLock<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Sync> Sync for WriteGuard<'_, T> {}
+
+impl<T> Deref for WriteGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for WriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for WriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.store(0, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn readers_share_writer_excludes() {
+        let x = RwSpinLock::new(5);
+        let r1 = x.read();
+        thread::scope(|s| {
+            // A second reader gets in while the first one is still held.
+            s.spawn(|| assert_eq!(*x.read(), 5));
+            s.spawn(|| assert!(x.try_write().is_none()));
+        });
+        assert_eq!(*r1, 5);
+        drop(r1);
+
+        let mut w = x.write();
+        *w += 1;
+        assert!(x.try_read().is_none());
+        assert!(x.try_write().is_none());
+        drop(w);
+        assert_eq!(*x.read(), 6);
+    }
+
+    #[test]
+    fn try_read_only_fails_for_a_writer() {
+        let x = RwSpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                // Readers racing on the count must not make each other fail.
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        assert!(x.try_read().is_some());
+                    }
+                });
+            }
+        });
+        let w = x.write();
+        assert!(x.try_read().is_none());
+        drop(w);
+    }
+
+    #[test]
+    fn concurrent_readers_and_writer() {
+        let x = RwSpinLock::new(Vec::new());
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..100 {
+                    x.write().push(i);
+                }
+            });
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        let v = x.read();
+                        // A writer never leaves the vec half-updated.
+                        assert!(v.iter().copied().eq(0..v.len() as i32));
+                    }
+                });
+            }
+        });
+        assert_eq!(x.read().len(), 100);
+    }
+
+    #[test]
+    fn try_upgradable_read_ignores_plain_readers() {
+        let x = RwSpinLock::new(0);
+        let done = std::sync::atomic::AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    while !done.load(Ordering::Relaxed) {
+                        drop(x.read());
+                    }
+                });
+            }
+            for _ in 0..1000 {
+                assert!(x.try_upgradable_read().is_some());
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+        let up = x.upgradable_read();
+        assert!(x.try_upgradable_read().is_none());
+        drop(up);
+    }
+
+    #[test]
+    fn upgradable_read_lets_readers_in_then_upgrades() {
+        let x = RwSpinLock::new(1);
+        let up = x.upgradable_read();
+        thread::scope(|s| {
+            s.spawn(|| assert_eq!(*x.read(), 1));
+            s.spawn(|| assert!(x.try_upgradable_read().is_none()));
+            s.spawn(|| assert!(x.try_write().is_none()));
+        });
+        let reading = std::sync::atomic::AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                let r = x.read();
+                reading.store(true, Ordering::Release);
+                thread::sleep(std::time::Duration::from_millis(20));
+                assert_eq!(*r, 1);
+            });
+            while !reading.load(Ordering::Acquire) {
+                thread::yield_now();
+            }
+            // Has to wait for the reader above to go away.
+            let mut w = up.upgrade();
+            *w += 1;
+        });
+        assert_eq!(*x.read(), 2);
+        drop(x.upgradable_read());
+        assert!(x.try_write().is_some());
+    }
+}
@@ -0,0 +1,100 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::Backoff;
+
+/// A counting semaphore that spins: up to `permits` holders at a time, for
+/// bounding how many threads use some resource at once.
+///
+/// Permits aren't tied to a guard, `release()` just hands one back, so it
+/// can also be released from another thread than the one that acquired it.
+///
+/// ```
+/// use my_spin_lock::SpinSemaphore;
+///
+/// let sem = SpinSemaphore::new(2);
+/// sem.acquire();
+/// assert!(sem.try_acquire());
+/// assert!(!sem.try_acquire());
+/// sem.release();
+/// assert_eq!(sem.available(), 1);
+/// ```
+#[derive(Debug)]
+pub struct SpinSemaphore {
+    permits: AtomicUsize,
+}
+
+impl SpinSemaphore {
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            permits: AtomicUsize::new(permits),
+        }
+    }
+
+    /// Spins until a permit is free and takes it.
+    pub fn acquire(&self) {
+        let mut backoff = Backoff::new();
+        while !self.try_acquire() {
+            backoff.snooze();
+        }
+    }
+
+    /// Single attempt, `true` if we got a permit.
+    pub fn try_acquire(&self) -> bool {
+        // A CAS rather than `fetch_sub`, so the count never wraps below 0.
+        self.permits
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok()
+    }
+
+    /// Hands a permit back. Nothing checks that one was taken: releasing
+    /// more than were acquired raises the limit.
+    pub fn release(&self) {
+        self.permits.fetch_add(1, Ordering::Release);
+    }
+
+    /// Permits free right now. Like `SpinLock::is_locked`, only a hint.
+    pub fn available(&self) -> usize {
+        self.permits.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn try_acquire_stops_at_zero() {
+        let sem = SpinSemaphore::new(1);
+        assert!(sem.try_acquire());
+        assert!(!sem.try_acquire());
+        assert_eq!(sem.available(), 0);
+        sem.release();
+        assert!(sem.try_acquire());
+    }
+
+    #[test]
+    fn at_most_permits_holders() {
+        const PERMITS: usize = 3;
+        let sem = SpinSemaphore::new(PERMITS);
+        let (inside, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..200 {
+                        sem.acquire();
+                        let now = inside.fetch_add(1, Ordering::Relaxed) + 1;
+                        most.fetch_max(now, Ordering::Relaxed);
+                        thread::yield_now();
+                        inside.fetch_sub(1, Ordering::Relaxed);
+                        sem.release();
+                    }
+                });
+            }
+        });
+        let most = most.into_inner();
+        assert!((1..=PERMITS).contains(&most), "{most} holders at once");
+        assert_eq!(sem.available(), PERMITS);
+    }
+}
@@ -0,0 +1,50 @@
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::SpinLock;
+
+/// Serializes the protected value, waiting for the lock like serde's own
+/// `Mutex` impl does. Fails if the lock is poisoned, since the data may be
+/// half-updated. Don't serialize a lock the current thread is holding, that
+/// spins forever.
+impl<T: Serialize, const B: usize> Serialize for SpinLock<T, B> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.lock_with_cas_checked() {
+            Ok(guard) => guard.serialize(serializer),
+            Err(_) => Err(ser::Error::custom(
+                "spinlock poisoned by a panicking thread",
+            )),
+        }
+    }
+}
+
+/// Deserializes a `T` and wraps it in a new, unlocked `SpinLock`.
+impl<'de, T: Deserialize<'de>, const B: usize> Deserialize<'de> for SpinLock<T, B> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(SpinLock::with_spin_budget)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use crate::SpinLock;
+
+    #[test]
+    fn round_trip() {
+        let x = SpinLock::new(vec![1u32, 2, 3]);
+        let json = serde_json::to_string(&x).unwrap();
+        assert_eq!(json, "[1,2,3]");
+        let y: SpinLock<Vec<u32>> = serde_json::from_str(&json).unwrap();
+        assert_eq!(y.into_inner(), [1, 2, 3]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn poisoned_lock_fails_to_serialize() {
+        let x = SpinLock::new(vec![1u32]);
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _g = x.lock_with_cas();
+            panic!("poison it");
+        }));
+        assert!(serde_json::to_string(&x).is_err());
+    }
+}
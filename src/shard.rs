@@ -0,0 +1,92 @@
+use crate::{Guard, SpinLock};
+
+/// Tries each of `shards` once, in order, without waiting, and returns the
+/// first one it got together with its index. `None` if all were held.
+///
+/// For work-stealing style code where any free shard will do: workers skip
+/// the busy ones instead of queueing up behind them. Held shards are told
+/// apart with a plain load first, so scanning past them doesn't write to
+/// their cache lines.
+pub fn try_lock_any<T, const B: usize>(
+    shards: &[SpinLock<T, B>],
+) -> Option<(usize, Guard<'_, T, B>)> {
+    shards
+        .iter()
+        .enumerate()
+        .filter(|(_, shard)| !shard.is_locked())
+        .find_map(|(i, shard)| Some((i, shard.try_lock()?)))
+}
+
+/// All of `locks` or none: tries each once without waiting and, if one is
+/// held, lets go of those it already got and returns `None`. For operations
+/// that need a whole set of resources at once and would rather retry later
+/// than wait while holding part of it.
+///
+/// The guards come back in slice order, which for a slice is also address
+/// order, the same order `lock!` takes locks in.
+#[cfg(feature = "std")]
+pub fn try_lock_all<T, const B: usize>(locks: &[SpinLock<T, B>]) -> Option<Vec<Guard<'_, T, B>>> {
+    // Dropping the partial `Vec` on failure releases what we have.
+    locks.iter().map(SpinLock::try_lock).collect()
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn skips_held_shards() {
+        let shards: [SpinLock<u32>; 4] = Default::default();
+        let g0 = shards[0].lock_with_cas();
+        let g2 = shards[2].lock_with_cas();
+        let (i, g1) = try_lock_any(&shards).unwrap();
+        assert_eq!(i, 1);
+        let (i, g3) = try_lock_any(&shards).unwrap();
+        assert_eq!(i, 3);
+        assert!(try_lock_any(&shards).is_none());
+        drop(g2);
+        assert_eq!(try_lock_any(&shards).unwrap().0, 2);
+        drop((g0, g1, g3));
+        assert!(try_lock_any::<u32, 0>(&[]).is_none());
+    }
+
+    #[test]
+    fn workers_spread_over_shards() {
+        let shards: [SpinLock<u32>; 4] = Default::default();
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut done = 0;
+                    while done < 500 {
+                        if let Some((_, mut g)) = try_lock_any(&shards) {
+                            *g += 1;
+                            done += 1;
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(shards.iter().map(|s| *s.lock_with_cas()).sum::<u32>(), 2000);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn try_lock_all_is_all_or_nothing() {
+        let locks: [SpinLock<u32>; 4] = Default::default();
+        let held = locks[2].lock_with_cas();
+        assert!(try_lock_all(&locks).is_none());
+        // The ones before the held lock were let go again.
+        assert!(!locks[0].is_locked() && !locks[1].is_locked() && !locks[3].is_locked());
+        drop(held);
+        let mut all = try_lock_all(&locks).unwrap();
+        assert_eq!(all.len(), 4);
+        for (i, g) in all.iter_mut().enumerate() {
+            **g = i as u32;
+        }
+        assert!(locks.iter().all(SpinLock::is_locked));
+        drop(all);
+        assert_eq!(*locks[3].lock_with_cas(), 3);
+    }
+}
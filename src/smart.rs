@@ -0,0 +1,173 @@
+use core::{
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    sync::atomic::Ordering,
+};
+use std::time::{Duration, Instant};
+
+use crate::{spin_loop, Guard, SpinLock};
+
+/// Holds longer than this are not worth spinning for at all: `lock_smart`
+/// yields right after its first failed attempt.
+const SPIN_LIMIT: Duration = Duration::from_micros(20);
+
+impl<T: ?Sized, const B: usize> SpinLock<T, B> {
+    /// A lock that learns how long it is usually held. Each `SmartGuard`
+    /// times its critical section and folds it into a moving average
+    /// (`hold_time`); a waiter then spins for up to twice that average
+    /// before it starts yielding, and doesn't spin at all (yields after
+    /// every failed attempt) once the average exceeds 20µs. So short
+    /// sections are waited out on the CPU, and long ones don't burn it.
+    ///
+    /// Only holds through `lock_smart` are timed, other `lock_*` calls
+    /// leave the average alone.
+    #[must_use = "the lock is released when the guard is dropped"]
+    #[track_caller]
+    pub fn lock_smart(&self) -> SmartGuard<'_, T, B> {
+        self.check_deadlock();
+        let guard = match self.try_lock() {
+            Some(guard) => guard,
+            None => self.wait_smart(),
+        };
+        SmartGuard {
+            guard: ManuallyDrop::new(guard),
+            start: Instant::now(),
+        }
+    }
+
+    #[track_caller]
+    fn wait_smart(&self) -> Guard<'_, T, B> {
+        self.record_spin();
+        let hold = self.hold_time();
+        let spin_for = if hold <= SPIN_LIMIT {
+            hold * 2
+        } else {
+            Duration::ZERO
+        };
+        let start = Instant::now();
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+            if start.elapsed() < spin_for {
+                spin_loop();
+            } else {
+                std::thread::yield_now();
+            }
+            self.record_spin();
+        }
+    }
+
+    /// The moving average `lock_smart` works with: every timed hold moves it
+    /// 1/8 of the way towards that hold's length (from zero, the first time).
+    pub fn hold_time(&self) -> Duration {
+        Duration::from_nanos(self.hold_nanos.load(Ordering::Relaxed))
+    }
+}
+
+/// A guard that times how long it is held, made by `SpinLock::lock_smart`.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct SmartGuard<'a, T: ?Sized, const B: usize = 0> {
+    // Dropped by hand, after the hold time is recorded.
+    guard: ManuallyDrop<Guard<'a, T, B>>,
+    start: Instant,
+}
+
+impl<T: ?Sized, const B: usize> Deref for SmartGuard<'_, T, B> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T: ?Sized, const B: usize> DerefMut for SmartGuard<'_, T, B> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T: ?Sized, const B: usize> Drop for SmartGuard<'_, T, B> {
+    fn drop(&mut self) {
+        let sample = u64::try_from(self.start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        let hold = &Guard::spinlock(&self.guard).hold_nanos;
+        // We still hold the lock, so nobody else is updating the average and
+        // plain loads and stores will do; waiters only read it as a hint.
+        let old = hold.load(Ordering::Relaxed);
+        let new = if old == 0 {
+            sample
+        } else {
+            old - old / 8 + sample / 8
+        };
+        hold.store(new, Ordering::Relaxed);
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn hold_time_follows_the_critical_sections() {
+        let x = SpinLock::new(0);
+        assert_eq!(x.hold_time(), Duration::ZERO);
+        {
+            let _g = x.lock_smart();
+            thread::sleep(Duration::from_millis(5));
+        }
+        let long = x.hold_time();
+        assert!(long >= Duration::from_millis(5), "{long:?}");
+        // Untimed locking doesn't count.
+        drop(x.lock_with_cas());
+        assert_eq!(x.hold_time(), long);
+        for _ in 0..100 {
+            *x.lock_smart() += 1;
+        }
+        // 100 near-zero samples leave (7/8)^100 of it, a few hundred ns at most.
+        assert!(x.hold_time() < long / 1000 + Duration::from_micros(50));
+        assert_eq!(x.into_inner(), 100);
+    }
+
+    #[test]
+    fn smart_under_contention() {
+        let x = SpinLock::new(0);
+        thread::scope(|s| {
+            for i in 0..4 {
+                let x = &x;
+                s.spawn(move || {
+                    for n in 0..200 {
+                        let mut g = x.lock_smart();
+                        // One thread has long sections now and then.
+                        if i == 0 && n % 50 == 0 {
+                            thread::sleep(Duration::from_millis(1));
+                        }
+                        *g += 1;
+                    }
+                });
+            }
+        });
+        assert!(x.hold_time() > Duration::ZERO);
+        assert_eq!(x.into_inner(), 800);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn waiting_still_reports_the_caller() {
+        let x = SpinLock::new(());
+        let g = x.lock_with_cas();
+        let line = thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                let (g, line) = (x.lock_smart(), line!());
+                drop(g);
+                line
+            });
+            thread::sleep(Duration::from_millis(20));
+            drop(g);
+            waiter.join().unwrap()
+        });
+        let at = x.last_locked_at().unwrap();
+        assert_eq!((at.file(), at.line()), (file!(), line));
+    }
+}
@@ -0,0 +1,108 @@
+use std::vec::Vec;
+
+use crate::SpinLock;
+
+/// A LIFO stack shared between threads: a `SpinLock<Vec<T>>` with each
+/// operation a short critical section of its own.
+///
+/// It's deliberately small, mostly as an example of wrapping the lock in a
+/// type whose methods never hand out guards, so callers can't hold the lock
+/// longer than one `push`/`pop`.
+pub struct SpinStack<T> {
+    items: SpinLock<Vec<T>>,
+}
+
+impl<T> SpinStack<T> {
+    const_fn! {
+        pub const fn new() -> Self {
+            Self {
+                items: SpinLock::new(Vec::new()),
+            }
+        }
+    }
+
+    pub fn push(&self, value: T) {
+        self.items.lock_with_cas().push(value);
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        self.items.lock_with_cas().pop()
+    }
+
+    /// A snapshot, other threads may push or pop right after.
+    pub fn len(&self) -> usize {
+        self.items.lock_with_cas().len()
+    }
+
+    /// A snapshot like `len`.
+    pub fn is_empty(&self) -> bool {
+        self.items.lock_with_cas().is_empty()
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        self.items.into_inner()
+    }
+}
+
+impl<T> Default for SpinStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn concurrent_pushes_all_land() {
+        let stack = SpinStack::new();
+        thread::scope(|s| {
+            for t in 0..4 {
+                let stack = &stack;
+                s.spawn(move || {
+                    for i in 0..250 {
+                        stack.push(t * 1000 + i);
+                    }
+                });
+            }
+        });
+        assert_eq!(stack.len(), 1000);
+        let mut all = stack.into_vec();
+        all.sort_unstable();
+        let expected: Vec<i32> = (0..4)
+            .flat_map(|t| (0..250).map(move |i| t * 1000 + i))
+            .collect();
+        assert_eq!(all, expected);
+    }
+
+    #[test]
+    fn pop_until_empty() {
+        let stack = SpinStack::new();
+        for i in 0..3 {
+            stack.push(i);
+        }
+        let popped: Vec<i32> = thread::scope(|s| {
+            let handles: Vec<_> = (0..2)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut mine = Vec::new();
+                        while let Some(v) = stack.pop() {
+                            mine.push(v);
+                        }
+                        mine
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect()
+        });
+        assert_eq!(popped.len(), 3);
+        assert!(stack.is_empty());
+        assert_eq!(stack.pop(), None);
+    }
+}
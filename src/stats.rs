@@ -0,0 +1,59 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A snapshot of a lock's contention counters, from `SpinLock::stats()`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LockStats {
+    /// How many guards have been handed out.
+    pub acquisitions: usize,
+    /// How many times a thread went round a spin loop waiting for the lock.
+    /// High compared to `acquisitions` means the lock is contended.
+    pub spins: usize,
+}
+
+/// The live counters inside a `SpinLock`. They are only statistics, so
+/// `Relaxed` is fine everywhere and the counts simply wrap on overflow.
+pub(crate) struct Counters {
+    acquisitions: AtomicUsize,
+    spins: AtomicUsize,
+}
+
+impl Counters {
+    pub(crate) const fn new() -> Self {
+        Self {
+            acquisitions: AtomicUsize::new(0),
+            spins: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn acquired(&self) {
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn spun(&self) {
+        self.spins.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> LockStats {
+        LockStats {
+            acquisitions: self.acquisitions.load(Ordering::Relaxed),
+            spins: self.spins.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl LockStats {
+    /// See `SpinLock::export_metrics`.
+    pub(crate) fn to_prometheus(self, name: &str) -> String {
+        // Label values escape exactly these three.
+        let name = name
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        format!(
+            "spinlock_acquisitions_total{{name=\"{name}\"}} {}\n\
+             spinlock_spins_total{{name=\"{name}\"}} {}\n",
+            self.acquisitions, self.spins
+        )
+    }
+}
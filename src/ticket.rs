@@ -0,0 +1,118 @@
+use core::{
+    cell::UnsafeCell,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// A fair spin lock: threads get the lock in the order they asked for it.
+///
+/// Works like the ticket machine at a bakery. `lock()` draws a ticket with
+/// `fetch_add` on `next_ticket`, then spins until `now_serving` shows that
+/// number. Dropping the guard calls the next number.
+pub struct TicketSpinLock<T> {
+    next_ticket: AtomicUsize,
+    now_serving: AtomicUsize,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for TicketSpinLock<T> where T: Send {}
+
+impl<T> TicketSpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            next_ticket: AtomicUsize::new(0),
+            now_serving: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Draws a ticket and waits for our turn. Tickets wrap around on overflow,
+    /// which is fine as long as fewer than `usize::MAX` threads wait at once.
+    #[must_use = "the lock is released when the guard is dropped"]
+    pub fn lock(&self) -> TicketGuard<'_, T> {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            core::hint::spin_loop();
+        }
+        TicketGuard {
+            lock: self,
+            _not_send: PhantomData,
+        }
+    }
+}
+
+/// Holds a `TicketSpinLock`; dropping it serves the next ticket.
+#[must_use = "the lock is released when the guard is dropped"]
+pub struct TicketGuard<'a, T> {
+    lock: &'a TicketSpinLock<T>,
+    _not_send: PhantomData<*const ()>,
+}
+
+unsafe impl<T: Sync> Sync for TicketGuard<'_, T> {}
+
+impl<T> Deref for TicketGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for TicketGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for TicketGuard<'_, T> {
+    fn drop(&mut self) {
+        // Only the holder writes `now_serving`, so a plain load + store is enough.
+        let next = self
+            .lock
+            .now_serving
+            .load(Ordering::Relaxed)
+            .wrapping_add(1);
+        self.lock.now_serving.store(next, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn served_in_ticket_order() {
+        let x = TicketSpinLock::new(Vec::new());
+        let g = x.lock();
+        thread::scope(|s| {
+            for i in 0..4 {
+                let x = &x;
+                s.spawn(move || x.lock().push(i));
+                // Wait until thread `i` has drawn its ticket before starting the
+                // next one, so ticket order is known to be 0, 1, 2, 3.
+                while x.next_ticket.load(Ordering::Relaxed) != i + 2 {
+                    thread::yield_now();
+                }
+            }
+            drop(g);
+        });
+        assert_eq!(*x.lock(), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn counts_under_contention() {
+        let x = TicketSpinLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *x.lock() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(*x.lock(), 4000);
+    }
+}
@@ -0,0 +1,67 @@
+//! Model-checks the lock's memory orderings with loom, which runs the test
+//! body under every possible thread interleaving.
+//!
+//! Run with:
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test --test loom --release
+//! ```
+//!
+//! The models use `lock_with_cas`: a failed `swap` in `lock_with_swap` is still
+//! a store, so loom can't tell a spinning thread from one making progress and
+//! gives up on the model.
+#![cfg(loom)]
+
+use loom::{sync::Arc, thread};
+use my_spin_lock::SpinLock;
+
+#[test]
+fn two_threads_increment() {
+    loom::model(|| {
+        let lock = Arc::new(SpinLock::new(0));
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let lock = lock.clone();
+                thread::spawn(move || *lock.lock_with_cas() += 1)
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(*lock.lock_with_cas(), 2);
+    });
+}
+
+#[test]
+fn two_threads_increment_ttas() {
+    loom::model(|| {
+        let lock = Arc::new(SpinLock::new(0));
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let lock = lock.clone();
+                thread::spawn(move || *lock.lock_ttas() += 1)
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(*lock.lock_ttas(), 2);
+    });
+}
+
+#[test]
+fn try_lock_sees_holder() {
+    loom::model(|| {
+        let lock = Arc::new(SpinLock::new(0));
+        let lock2 = lock.clone();
+        let t = thread::spawn(move || {
+            if let Some(mut g) = lock2.try_lock() {
+                *g += 1;
+            }
+        });
+        *lock.lock_with_cas() += 1;
+        t.join().unwrap();
+        let n = *lock.lock_with_cas();
+        assert!(n == 1 || n == 2);
+    });
+}
@@ -0,0 +1,85 @@
+//! Plain (non-atomic) data behind the locks, hammered from many threads.
+//! Run under ThreadSanitizer, which reports a data race if the `Acquire` on
+//! lock and `Release` on unlock ever fail to order the critical sections:
+//!
+//! ```sh
+//! RUSTFLAGS="-Zsanitizer=thread --cfg tsan" \
+//!     cargo +nightly test -Zbuild-std --target x86_64-unknown-linux-gnu --test tsan
+//! ```
+#![cfg(tsan)]
+
+use std::{cell::UnsafeCell, thread};
+
+use my_spin_lock::{RawSpinLock, SpinLock, TicketSpinLock};
+
+const THREADS: usize = 8;
+// Kept small: under TSan every spin is instrumented, and a single core spends
+// most of its time spinning on a lock whose holder got preempted.
+const ITERS: usize = 200;
+
+/// Runs `incr` `ITERS` times on each of `THREADS` threads.
+fn hammer(incr: impl Fn() + Sync) {
+    thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..ITERS {
+                    incr();
+                }
+            });
+        }
+    });
+}
+
+#[test]
+fn spinlock_cas() {
+    let x = SpinLock::new(0usize);
+    hammer(|| *x.lock_with_cas() += 1);
+    assert_eq!(x.into_inner(), THREADS * ITERS);
+}
+
+#[test]
+fn spinlock_swap() {
+    let x = SpinLock::new(0usize);
+    hammer(|| *x.lock_with_swap() += 1);
+    assert_eq!(x.into_inner(), THREADS * ITERS);
+}
+
+#[test]
+fn spinlock_backoff() {
+    let x = SpinLock::new(0usize);
+    hammer(|| *x.lock_with_backoff() += 1);
+    assert_eq!(x.into_inner(), THREADS * ITERS);
+}
+
+#[test]
+fn raw_spinlock_external_data() {
+    struct Counter {
+        lock: RawSpinLock,
+        count: UnsafeCell<usize>,
+    }
+    unsafe impl Sync for Counter {}
+
+    impl Counter {
+        fn incr(&self) {
+            self.lock.lock();
+            unsafe {
+                *self.count.get() += 1;
+                self.lock.unlock();
+            }
+        }
+    }
+
+    let c = Counter {
+        lock: RawSpinLock::new(),
+        count: UnsafeCell::new(0),
+    };
+    hammer(|| c.incr());
+    assert_eq!(c.count.into_inner(), THREADS * ITERS);
+}
+
+#[test]
+fn ticket_spinlock() {
+    let x = TicketSpinLock::new(0usize);
+    hammer(|| *x.lock() += 1);
+    assert_eq!(*x.lock(), THREADS * ITERS);
+}